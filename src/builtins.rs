@@ -0,0 +1,215 @@
+use anyhow::{bail, ensure, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+/// Names of the built-in variables that can be used with `#{@name}`
+pub const NAMES: &[&str] = &[
+    "project",
+    "project_dir",
+    "projects_dir",
+    "template",
+    "date",
+    "year",
+    "user",
+    "git_name",
+    "git_email",
+    "hostname",
+    "os",
+    "uuid",
+];
+
+/// Format of `@date` when none is given
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Checks that `name` is a built-in variable and that it accepts `format` if there is one
+pub fn validate(name: &str, format: Option<&str>) -> Result<()> {
+    ensure!(NAMES.contains(&name), "unknown built-in variable `@{name}`");
+    if let Some(format) = format {
+        ensure!(name == "date", "`@{name}` doesn't accept a format");
+        if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+            bail!("invalid date format `{format}`")
+        }
+    }
+    Ok(())
+}
+
+/// Built-in variables that are slow to find, since they run git or look up the hostname
+///
+/// They are only found the first time they are used.
+const LAZY: [&str; 3] = ["git_name", "git_email", "hostname"];
+
+/// The values of the built-in variables while creating a project
+///
+/// They are computed once, so that e.g. every command gets the same `@uuid`.
+#[derive(Debug)]
+pub struct Builtins {
+    values: HashMap<&'static str, String>,
+    lazy_values: [OnceLock<Option<String>>; LAZY.len()],
+    now: DateTime<Local>,
+}
+
+impl Builtins {
+    pub fn new(template: &str, project: &str, project_dir: &Path, projects_dir: &Path) -> Self {
+        let now = Local::now();
+        let mut values = HashMap::from([
+            ("project", project.to_string()),
+            ("project_dir", project_dir.to_string_lossy().to_string()),
+            ("projects_dir", projects_dir.to_string_lossy().to_string()),
+            ("template", template.to_string()),
+            ("date", now.format(DEFAULT_DATE_FORMAT).to_string()),
+            ("year", now.format("%Y").to_string()),
+            ("os", std::env::consts::OS.to_string()),
+            ("uuid", uuid::Uuid::new_v4().to_string()),
+        ]);
+        if let Some(user) = user() {
+            values.insert("user", user);
+        }
+        Self {
+            values,
+            lazy_values: Default::default(),
+            now,
+        }
+    }
+
+    /// Returns the value of the variable `name`, formatted with `format` if it's a date
+    pub fn get(&self, name: &str, format: Option<&str>) -> Option<String> {
+        match (name, format) {
+            ("date", Some(format)) => Some(self.now.format(format).to_string()),
+            _ => self
+                .values
+                .get(name)
+                .map(String::as_str)
+                .or_else(|| self.lazy_value(name))
+                .map(str::to_string),
+        }
+    }
+
+    /// Returns the variables as `QK_<NAME>` environment variables for the command `line`
+    ///
+    /// `@project` is `QK_PROJECT_NAME`, to not be confused with `QK_PROJECT_DIR`. The slow
+    /// variables are only set if `line` mentions them.
+    pub fn env_vars(&self, line: &str) -> Vec<(String, &str)> {
+        let values = self
+            .values
+            .iter()
+            .map(|(name, value)| (env_var_name(name), value.as_str()));
+        let lazy_values = LAZY.iter().filter_map(|name| {
+            let env_name = env_var_name(name);
+            line.contains(&env_name)
+                .then(|| self.lazy_value(name))
+                .flatten()
+                .map(|value| (env_name, value))
+        });
+        values.chain(lazy_values).collect()
+    }
+
+    /// Returns the value of the slow variable `name`, finding it if it's the first time
+    fn lazy_value(&self, name: &str) -> Option<&str> {
+        let index = LAZY.iter().position(|lazy_name| *lazy_name == name)?;
+        self.lazy_values[index]
+            .get_or_init(|| match name {
+                "git_name" => git_config("user.name"),
+                "git_email" => git_config("user.email"),
+                _ => hostname(),
+            })
+            .as_deref()
+    }
+}
+
+/// Returns the name of the environment variable of the built-in variable `name`
+fn env_var_name(name: &str) -> String {
+    let name = match name {
+        "project" => "project_name",
+        name => name,
+    };
+    format!("QK_{}", name.to_uppercase())
+}
+
+fn user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+        .filter(|user| !user.is_empty())
+}
+
+fn hostname() -> Option<String> {
+    hostname::get().ok()?.into_string().ok()
+}
+
+/// Returns the value of `key` in the git config, if git is installed and it's set
+fn git_config(key: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["config", "--get", key])
+        .output()
+        .ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("project", None).is_ok());
+        assert!(validate("date", Some("%Y/%m")).is_ok());
+        assert_eq!(
+            validate("unknown", None).map_err(|err| err.to_string()),
+            Err("unknown built-in variable `@unknown`".to_string())
+        );
+        assert_eq!(
+            validate("year", Some("%Y")).map_err(|err| err.to_string()),
+            Err("`@year` doesn't accept a format".to_string())
+        );
+        assert_eq!(
+            validate("date", Some("%Q")).map_err(|err| err.to_string()),
+            Err("invalid date format `%Q`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_builtins() {
+        let builtins = Builtins::new("rs", "app", Path::new("/a/app"), Path::new("/a"));
+        assert_eq!(builtins.get("project", None), Some("app".to_string()));
+        assert_eq!(builtins.get("template", None), Some("rs".to_string()));
+        assert_eq!(builtins.get("date", Some("%Y")), builtins.get("year", None));
+        assert_eq!(builtins.get("uuid", None), builtins.get("uuid", None));
+        assert!(builtins
+            .env_vars("")
+            .contains(&("QK_PROJECT_NAME".to_string(), "app")));
+    }
+
+    #[test]
+    fn test_builtins_lazy_values() {
+        let builtins = Builtins::new("rs", "app", Path::new("/a/app"), Path::new("/a"));
+        assert!(builtins
+            .lazy_values
+            .iter()
+            .all(|value| value.get().is_none()));
+        assert!(!builtins
+            .env_vars("echo $QK_PROJECT_NAME")
+            .iter()
+            .any(|(name, _)| name == "QK_HOSTNAME"));
+        assert!(builtins
+            .lazy_values
+            .iter()
+            .all(|value| value.get().is_none()));
+
+        let hostname = builtins.get("hostname", None);
+        assert_eq!(builtins.lazy_values[2].get(), Some(&hostname));
+        assert_eq!(
+            builtins
+                .env_vars("echo $QK_HOSTNAME")
+                .into_iter()
+                .find(|(name, _)| name == "QK_HOSTNAME")
+                .map(|(_, value)| value.to_string()),
+            hostname
+        );
+    }
+}
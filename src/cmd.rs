@@ -1,10 +1,14 @@
-use clap::{crate_name, crate_version, Arg, Command};
+use clap::{crate_name, crate_version, Arg, ArgMatches, Command};
+
+use std::env;
 
 const USAGE: &str = "\
     qk [OPTIONS] <template> <project> [custom-args]...
+    qk [OPTIONS] <project>
     qk [OPTIONS] -L <template>
     qk [OPTIONS] -E <template>
-    qk [OPTIONS] -T
+    qk [OPTIONS] -T [--long] [--tag <tag>]...
+    qk [OPTIONS] config <command>
     qk --help
     qk --version
 ";
@@ -13,11 +17,35 @@ const MAIN_OPERATION: &[&str; 3] = &["project", "custom-args", "overwrite"];
 const OTHER_OPERATIONS: &[&str; 3] = &["list-projects", "list-templates", "show-editor"];
 const COMMANDS_HEADING: &str = "Commands";
 
+/// Parses the arguments of the process
+///
+/// `config` is the subcommand only before the template, so that `qk rs config` still creates a
+/// project named `config`.
+pub fn get_matches() -> ArgMatches {
+    let args: Vec<_> = env::args_os().collect();
+    match cmd()
+        .args_conflicts_with_subcommands(true)
+        .try_get_matches_from(&args)
+    {
+        Ok(matches)
+            if matches
+                .get_one::<String>("template")
+                .is_some_and(|template| template != "config") =>
+        {
+            matches
+        }
+        _ => cmd().get_matches_from(args),
+    }
+}
+
 pub fn cmd() -> Command {
     Command::new(crate_name!())
         .version(crate_version!())
         .about("qk allows you to quickly create new projects using templates")
         .override_usage(USAGE)
+        .subcommand_negates_reqs(true)
+        .disable_help_subcommand(true)
+        .subcommand(config_cmd())
         .arg(
             Arg::new("template")
                 .required_unless_present("list-templates")
@@ -27,10 +55,9 @@ pub fn cmd() -> Command {
         )
         .arg(
             Arg::new("project")
-                .required_unless_present_any(OTHER_OPERATIONS)
                 .value_parser(clap::builder::NonEmptyStringValueParser::new())
                 .action(clap::ArgAction::Set)
-                .help("The name of the project to create/open"),
+                .help("The name of the project to create/open, the only name if default_template is set"),
         )
         .arg(
             Arg::new("custom-args")
@@ -63,6 +90,8 @@ pub fn cmd() -> Command {
                 .long_help(
                     "Editor to open in this project's directory. \
                     Set this to an empty string to skip opening an editor. \
+                    It can contain arguments, `{dir}` is replaced by the project's directory, \
+                    which otherwise is passed as the last argument. \
                     If not specified, it will be searched in these places in order:\
                     \n\t- Template editor in config\
                     \n\t- Default editor in config\
@@ -116,6 +145,18 @@ pub fn cmd() -> Command {
                 .help_heading(COMMANDS_HEADING)
                 .help("List templates in config"),
         )
+        .arg(
+            Arg::new("long")
+                .long("long")
+                .action(clap::ArgAction::SetTrue)
+                .help("With -T, show a table with the description, projects_dir, number of projects and custom arguments of each template"),
+        )
+        .arg(
+            Arg::new("tag")
+                .long("tag")
+                .action(clap::ArgAction::Append)
+                .help("With -T, only list the templates with this tag, can be repeated"),
+        )
         .arg(
             Arg::new("show-editor")
                 .short('E')
@@ -129,6 +170,99 @@ pub fn cmd() -> Command {
         )
 }
 
+fn config_cmd() -> Command {
+    Command::new("config")
+        .about("Change the config file, keeping its comments and formatting")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("init")
+                .about("Create the config file with starter templates for the installed toolchains")
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::builder::PossibleValuesParser::new(
+                            qk::init::Toolchain::names(),
+                        ))
+                        .help("Add a template for this toolchain instead of the installed ones, can be repeated"),
+                )
+                .arg(
+                    Arg::new("interactive")
+                        .short('i')
+                        .long("interactive")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Ask which templates to add and where their projects go"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Overwrite the config file if it already exists"),
+                ),
+        )
+        .subcommand(
+            Command::new("add-template")
+                .about("Add a template")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .help("The name of the template"),
+                )
+                .arg(
+                    Arg::new("projects-dir")
+                        .long("projects-dir")
+                        .required(true)
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .help("The directory where new projects will be created"),
+                )
+                .arg(
+                    Arg::new("command")
+                        .long("command")
+                        .action(clap::ArgAction::Append)
+                        .help("A command to execute when creating a project, can be repeated"),
+                ),
+        )
+        .subcommand(
+            Command::new("remove-template")
+                .about("Remove a template")
+                .arg(
+                    Arg::new("name")
+                        .required(true)
+                        .help("The name of the template"),
+                ),
+        )
+        .subcommand(
+            Command::new("set")
+                .about("Set the editor or shell, or the projects_dir of a template")
+                .arg(Arg::new("key").required(true).value_parser(
+                    clap::builder::PossibleValuesParser::new(qk::config_file::SETTABLE_KEYS),
+                ))
+                .arg(Arg::new("value").required(true))
+                .arg(
+                    Arg::new("template")
+                        .short('t')
+                        .long("template")
+                        .help("Set it in this template instead of for all templates"),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Show the settings that would be used and where they come from")
+                .arg(
+                    Arg::new("template")
+                        .help("Show the settings for this template instead of for all templates"),
+                ),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Print the JSON Schema of the config, for editors to validate it"),
+        )
+        .subcommand(Command::new("edit").about(
+            "Open the config file in the editor, the same one that would open for a project",
+        ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::cmd;
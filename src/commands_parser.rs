@@ -1,18 +1,167 @@
 use std::collections::HashSet;
+use std::fmt::{self, Display, Write};
 
 use anyhow::{bail, ensure, Context, Result};
+use regex::Regex;
+
+use crate::builtins::{self, Builtins};
+use crate::filters::Filter;
 
 pub type Command = Vec<Unit>;
 
+/// Prefix of the name of a flag that is set by default and unset with `--no-<name>`
+pub const NEGATABLE_PREFIX: &str = "[no-]";
+
+/// A parsed command of a template
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CommandLine {
+    /// Executed with the template's shell
+    Shell(Command),
+    /// Executed directly, each element is exactly one argument
+    Argv(Vec<Command>),
+}
+
+impl CommandLine {
+    /// Returns all the units in this command, including the nested ones
+    pub fn units(&self) -> Vec<&Unit> {
+        match self {
+            CommandLine::Shell(command) => command.iter().flat_map(Unit::flatten).collect(),
+            CommandLine::Argv(args) => args.iter().flatten().flat_map(Unit::flatten).collect(),
+        }
+    }
+
+    /// Calls `f` with every custom argument in this command, including the nested ones
+    pub fn visit_args_mut(&mut self, f: &mut impl FnMut(&mut Unit) -> Result<()>) -> Result<()> {
+        match self {
+            CommandLine::Shell(command) => command
+                .iter_mut()
+                .try_for_each(|unit| unit.visit_args_mut(f)),
+            CommandLine::Argv(args) => args
+                .iter_mut()
+                .flatten()
+                .try_for_each(|unit| unit.visit_args_mut(f)),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Unit {
     Text(String),
+    Builtin(Builtin),
     Positional(ClapPositional),
     Option(ClapOption),
     Flag(ClapFlag),
+    Conditional(Conditional),
+    Expression(Expression),
 }
 
 impl Unit {
+    /// Returns a flag with only a long version, negatable if `name` starts with `[no-]`
+    pub fn flag(name: &str) -> Unit {
+        let (name, negatable) = match name.strip_prefix(NEGATABLE_PREFIX) {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        Unit::Flag(ClapFlag {
+            name: name.to_string(),
+            long: Some(name.to_string()),
+            short: None,
+            text: None,
+            negatable,
+            env: None,
+        })
+    }
+
+    /// Returns a positional argument without values of a specific type
+    pub fn positional(name: &str, index: usize, required: bool) -> Unit {
+        Unit::Positional(ClapPositional {
+            name: name.to_string(),
+            allow_empty_values: false,
+            required,
+            index,
+            multiple: None,
+            value_type: None,
+            env: None,
+        })
+    }
+
+    /// Returns an option without values of a specific type
+    pub fn option(name: &str, long: Option<&str>, short: Option<char>, required: bool) -> Unit {
+        Unit::Option(ClapOption {
+            name: name.to_string(),
+            long: long.map(String::from),
+            short,
+            allow_empty_values: false,
+            required,
+            multiple: None,
+            value_type: None,
+            env: None,
+        })
+    }
+
+    /// Returns a flag that expands to its long version, or to its short one if it has no long
+    pub fn named_flag(name: &str, long: Option<&str>, short: Option<char>) -> Unit {
+        Unit::Flag(ClapFlag {
+            name: name.to_string(),
+            long: long.map(String::from),
+            short,
+            text: None,
+            negatable: false,
+            env: None,
+        })
+    }
+
+    /// Returns the name of the custom argument, if this is one
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Unit::Positional(unit) => Some(&unit.name),
+            Unit::Option(unit) => Some(&unit.name),
+            Unit::Flag(unit) => Some(&unit.name),
+            Unit::Text(_) | Unit::Builtin(_) | Unit::Conditional(_) | Unit::Expression(_) => None,
+        }
+    }
+
+    /// Returns the name of the custom argument referenced by this expression, if any
+    pub fn reference(&self) -> Option<&str> {
+        match self {
+            Unit::Expression(
+                Expression::Value(Reference::Arg(name), _)
+                | Expression::If(Reference::Arg(name), _, _)
+                | Expression::Each(Reference::Arg(name), _),
+            ) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Returns this unit followed by the units nested in it
+    pub fn flatten(&self) -> Vec<&Unit> {
+        let mut units = vec![self];
+        let nested: Vec<&Unit> = match self {
+            Unit::Conditional(conditional) => std::iter::once(conditional.arg.as_ref())
+                .chain(&conditional.body)
+                .collect(),
+            Unit::Expression(Expression::If(_, then, otherwise)) => {
+                then.iter().chain(otherwise).collect()
+            }
+            Unit::Expression(Expression::Each(_, body)) => body.iter().collect(),
+            _ => Vec::new(),
+        };
+        units.extend(nested.into_iter().flat_map(Unit::flatten));
+        units
+    }
+
+    /// Returns whether the custom argument was used
+    ///
+    /// Units that are not custom arguments are always set.
+    pub fn is_set(&self, matches: &clap::ArgMatches) -> bool {
+        match self {
+            Unit::Positional(ClapPositional { name, .. })
+            | Unit::Option(ClapOption { name, .. }) => matches.get_raw(name).is_some(),
+            Unit::Flag(unit) => matches.get_flag(&unit.name),
+            Unit::Text(_) | Unit::Builtin(_) | Unit::Conditional(_) | Unit::Expression(_) => true,
+        }
+    }
+
     pub fn to_clap_args(units: HashSet<&Unit>) -> Vec<clap::Arg> {
         units
             .iter()
@@ -22,7 +171,15 @@ impl Unit {
                         .action(clap::ArgAction::Set)
                         .required(unit.required)
                         .index(unit.index);
-                    if !unit.allow_empty_values {
+                    if unit.multiple.is_some() {
+                        arg = arg.action(clap::ArgAction::Append).num_args(1..);
+                    }
+                    if let Some(env) = &unit.env {
+                        arg = arg.env(env);
+                    }
+                    if let Some(value_type) = &unit.value_type {
+                        arg = arg.value_parser(value_type.value_parser(unit.allow_empty_values))
+                    } else if !unit.allow_empty_values {
                         arg = arg.value_parser(clap::builder::NonEmptyStringValueParser::new())
                     }
                     Some(arg)
@@ -31,7 +188,15 @@ impl Unit {
                     let mut arg = clap::Arg::new(&unit.name)
                         .action(clap::ArgAction::Set)
                         .required(unit.required);
-                    if !unit.allow_empty_values {
+                    if unit.multiple.is_some() {
+                        arg = arg.action(clap::ArgAction::Append);
+                    }
+                    if let Some(env) = &unit.env {
+                        arg = arg.env(env);
+                    }
+                    if let Some(value_type) = &unit.value_type {
+                        arg = arg.value_parser(value_type.value_parser(unit.allow_empty_values))
+                    } else if !unit.allow_empty_values {
                         arg = arg.value_parser(clap::builder::NonEmptyStringValueParser::new())
                     }
                     if let Some(long) = &unit.long {
@@ -44,8 +209,18 @@ impl Unit {
                 }
                 Unit::Flag(unit) => {
                     let mut arg = clap::Arg::new(&unit.name).action(clap::ArgAction::SetTrue);
+                    if unit.negatable {
+                        arg = arg.action(clap::ArgAction::SetFalse);
+                    }
+                    if let Some(env) = &unit.env {
+                        arg = arg.env(env);
+                    }
                     if let Some(long) = &unit.long {
-                        arg = arg.long(long);
+                        if unit.negatable {
+                            arg = arg.long(format!("no-{long}"));
+                        } else {
+                            arg = arg.long(long);
+                        }
                     }
                     if let Some(short) = unit.short {
                         arg = arg.short(short)
@@ -57,30 +232,368 @@ impl Unit {
             .collect()
     }
 
-    pub fn to_value(&self, matches: &clap::ArgMatches) -> Option<String> {
+    pub fn to_value(&self, matches: &clap::ArgMatches, builtins: &Builtins) -> Option<String> {
+        self.resolve(matches, builtins, None)
+    }
+
+    /// Returns the value of this unit, `this` being the current value of a `{{#each}}`
+    fn resolve(
+        &self,
+        matches: &clap::ArgMatches,
+        builtins: &Builtins,
+        this: Option<&str>,
+    ) -> Option<String> {
         match self {
             Unit::Text(text) => Some(text.to_owned()),
-            Unit::Positional(unit) => matches.get_one::<String>(&unit.name).cloned(),
-            Unit::Option(unit) => matches.get_one::<String>(&unit.name).cloned(),
+            Unit::Builtin(builtin) => builtins.get(&builtin.name, builtin.format.as_deref()),
+            Unit::Positional(_) | Unit::Option(_) => {
+                self.to_values(matches).map(|values| values.join(" "))
+            }
             Unit::Flag(unit) => {
                 if *matches.get_one::<bool>(&unit.name).unwrap() {
-                    let prefix = if unit.long.is_none() { "-" } else { "--" };
-                    let unit = format!("{}{}", prefix, unit.name);
-                    Some(unit)
+                    if let Some(text) = &unit.text {
+                        return Some(text.clone());
+                    }
+                    Some(switch(&unit.name, unit.long.as_deref()))
                 } else {
                     None
                 }
             }
+            Unit::Conditional(conditional) => {
+                if conditional.arg.is_set(matches) {
+                    let body = conditional
+                        .body
+                        .iter()
+                        .filter_map(|unit| unit.resolve(matches, builtins, this))
+                        .collect();
+                    Some(body)
+                } else {
+                    None
+                }
+            }
+            Unit::Expression(expression) => expression.resolve(matches, builtins, this),
+        }
+    }
+
+    /// Returns the words of a custom argument that accepts several values
+    ///
+    /// Each value is its own word, preceded by the option if it is repeated, unless they are
+    /// joined with a separator. Returns `None` for units that aren't positionals or options.
+    pub fn to_values(&self, matches: &clap::ArgMatches) -> Option<Vec<String>> {
+        let (name, multiple, option) = match self {
+            Unit::Positional(unit) => (&unit.name, &unit.multiple, None),
+            Unit::Option(unit) => (
+                &unit.name,
+                &unit.multiple,
+                Some(switch(&unit.name, unit.long.as_deref())),
+            ),
+            _ => return None,
+        };
+        let values = matches.get_many::<String>(name)?.cloned();
+        Some(match (multiple, option) {
+            (Some(Multiple::Join(separator)), _) => {
+                vec![values.collect::<Vec<_>>().join(separator)]
+            }
+            (Some(Multiple::Repeat), Some(option)) => {
+                values.flat_map(|value| [option.clone(), value]).collect()
+            }
+            _ => values.collect(),
+        })
+    }
+
+    /// Returns whether the values of this unit must be separate arguments in argv commands
+    pub fn is_repeated(&self) -> bool {
+        matches!(
+            self,
+            Unit::Positional(ClapPositional {
+                multiple: Some(Multiple::Repeat),
+                ..
+            }) | Unit::Option(ClapOption {
+                multiple: Some(Multiple::Repeat),
+                ..
+            })
+        )
+    }
+}
+
+/// Returns how an option or flag is written in the command line, `--long` or `-short`
+///
+/// Options and flags without a long version are named after the short one.
+fn switch(name: &str, long: Option<&str>) -> String {
+    match long {
+        Some(long) => format!("--{long}"),
+        None => format!("-{name}"),
+    }
+}
+
+/// How the values of a custom argument that accepts several of them are combined
+///
+/// Example: `#{feature+}` is repeated (`--feature a --feature b`) and `#{feature+","}` is joined
+/// (`a,b`)
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Multiple {
+    Repeat,
+    Join(String),
+}
+
+/// Type of the values of a custom argument, checked before running any command
+///
+/// Example: `#{port:int}` or `#{name~^[a-z]+$}`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum ValueType {
+    Int,
+    Bool,
+    /// A path that exists
+    Path,
+    Url,
+    Semver,
+    /// A value matching the regex
+    Regex(String),
+}
+
+impl ValueType {
+    /// Names of the types that can be used with `#{name:type}`
+    pub const NAMES: &'static [&'static str] = &["int", "bool", "path", "url", "semver"];
+
+    /// Returns the name used with `#{name:type}`, or `None` for regexes which use `~`
+    fn name(&self) -> Option<&'static str> {
+        Some(match self {
+            ValueType::Int => "int",
+            ValueType::Bool => "bool",
+            ValueType::Path => "path",
+            ValueType::Url => "url",
+            ValueType::Semver => "semver",
+            ValueType::Regex(_) => return None,
+        })
+    }
+
+    fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "int" => ValueType::Int,
+            "bool" => ValueType::Bool,
+            "path" => ValueType::Path,
+            "url" => ValueType::Url,
+            "semver" => ValueType::Semver,
+            _ => bail!(
+                "unknown type `{name}`, expected one of: {}",
+                Self::NAMES.join(", ")
+            ),
+        })
+    }
+
+    /// Returns a parser that validates the values and returns them as strings
+    ///
+    /// Ints and bools are normalized, e.g. `yes` becomes `true`. Empty values are accepted without
+    /// validating them if `allow_empty_values`, and rejected otherwise.
+    fn value_parser(&self, allow_empty_values: bool) -> clap::builder::ValueParser {
+        use clap::builder::{BoolishValueParser, TypedValueParser};
+
+        match self {
+            ValueType::Int => AllowingEmpty::value_parser(
+                allow_empty_values,
+                clap::value_parser!(i64).map(|value| value.to_string()),
+            ),
+            ValueType::Bool => AllowingEmpty::value_parser(
+                allow_empty_values,
+                BoolishValueParser::new().map(|value| value.to_string()),
+            ),
+            ValueType::Path => AllowingEmpty::value_parser(allow_empty_values, |value: &str| {
+                if std::path::Path::new(value).exists() {
+                    Ok(value.to_string())
+                } else {
+                    Err(format!("path {value:?} doesn't exist"))
+                }
+            }),
+            ValueType::Url => AllowingEmpty::value_parser(allow_empty_values, |value: &str| {
+                url::Url::parse(value)
+                    .map(|_| value.to_string())
+                    .map_err(|err| err.to_string())
+            }),
+            ValueType::Semver => AllowingEmpty::value_parser(allow_empty_values, |value: &str| {
+                semver::Version::parse(value)
+                    .map(|_| value.to_string())
+                    .map_err(|err| err.to_string())
+            }),
+            ValueType::Regex(pattern) => {
+                // The pattern was already validated when parsing the command
+                let regex = Regex::new(pattern).expect("invalid regex");
+                AllowingEmpty::value_parser(allow_empty_values, move |value: &str| {
+                    if regex.is_match(value) {
+                        Ok(value.to_string())
+                    } else {
+                        Err(format!("value doesn't match the pattern `{regex}`"))
+                    }
+                })
+            }
         }
     }
 }
 
+/// Parses values with `parser`, except for empty values
+#[derive(Clone)]
+struct AllowingEmpty<P> {
+    allow: bool,
+    parser: P,
+}
+
+impl<P> AllowingEmpty<P>
+where
+    P: clap::builder::TypedValueParser<Value = String>,
+{
+    fn value_parser(allow: bool, parser: P) -> clap::builder::ValueParser {
+        clap::builder::ValueParser::new(Self { allow, parser })
+    }
+}
+
+impl<P> clap::builder::TypedValueParser for AllowingEmpty<P>
+where
+    P: clap::builder::TypedValueParser<Value = String>,
+{
+    type Value = String;
+
+    fn parse_ref(
+        &self,
+        cmd: &clap::Command,
+        arg: Option<&clap::Arg>,
+        value: &std::ffi::OsStr,
+    ) -> Result<String, clap::Error> {
+        if value.is_empty() {
+            return match self.allow {
+                true => Ok(String::new()),
+                // Fails with the same error as untyped arguments
+                false => clap::builder::NonEmptyStringValueParser::new().parse_ref(cmd, arg, value),
+            };
+        }
+        self.parser.parse_ref(cmd, arg, value)
+    }
+}
+
+/// A built-in variable
+///
+/// Example: `#{@project}` or `#{@date:%Y}`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Builtin {
+    name: String,
+    format: Option<String>,
+}
+
+impl Builtin {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            format: None,
+        }
+    }
+}
+
+/// An expression in `{{ }}`, which uses custom arguments declared with `#{}` elsewhere
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Expression {
+    /// `{{name | filter}}`
+    Value(Reference, Vec<Filter>),
+    /// `{{#if name}}then{{else}}otherwise{{/if}}`
+    If(Reference, Command, Command),
+    /// `{{#each name}}body with {{this}}{{/each}}`
+    Each(Reference, Command),
+}
+
+/// What an expression refers to
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum Reference {
+    /// A custom argument: `name`
+    Arg(String),
+    /// A built-in variable: `@name`
+    Builtin(Builtin),
+    /// The current value in `{{#each}}`: `this`
+    This,
+}
+
+impl Reference {
+    /// Returns the values of the reference, which are empty if it isn't set
+    fn values(
+        &self,
+        matches: &clap::ArgMatches,
+        builtins: &Builtins,
+        this: Option<&str>,
+    ) -> Vec<String> {
+        match self {
+            Reference::Arg(name) => {
+                if let Ok(Some(values)) = matches.try_get_many::<String>(name) {
+                    values.cloned().collect()
+                } else if let Ok(Some(true)) = matches.try_get_one::<bool>(name) {
+                    vec![String::from("true")]
+                } else {
+                    Vec::new()
+                }
+            }
+            Reference::Builtin(builtin) => builtins
+                .get(&builtin.name, builtin.format.as_deref())
+                .into_iter()
+                .collect(),
+            Reference::This => this.map(String::from).into_iter().collect(),
+        }
+    }
+}
+
+impl Expression {
+    fn resolve(
+        &self,
+        matches: &clap::ArgMatches,
+        builtins: &Builtins,
+        this: Option<&str>,
+    ) -> Option<String> {
+        // Like the units of a command, a body is `None` if none of its units have a value
+        let resolve_body = |body: &Command, this: Option<&str>| {
+            body.iter()
+                .filter_map(|unit| unit.resolve(matches, builtins, this))
+                .reduce(|body, value| body + &value)
+        };
+        match self {
+            Expression::Value(reference, filters) => {
+                let values = reference.values(matches, builtins, this);
+                if values.is_empty() {
+                    return None;
+                }
+                let value = filters
+                    .iter()
+                    .fold(values.join(" "), |value, filter| filter.apply(&value));
+                Some(value)
+            }
+            Expression::If(reference, then, otherwise) => {
+                if reference.values(matches, builtins, this).is_empty() {
+                    resolve_body(otherwise, this)
+                } else {
+                    resolve_body(then, this)
+                }
+            }
+            Expression::Each(reference, body) => reference
+                .values(matches, builtins, this)
+                .iter()
+                .filter_map(|value| resolve_body(body, Some(value)))
+                .reduce(|body, value| body + &value),
+        }
+    }
+}
+
+/// Text that is only included if a custom argument is used
+///
+/// Example: `#{docker? && docker build .}`
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct Conditional {
+    arg: Box<Unit>,
+    body: Command,
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct ClapPositional {
     name: String,
     allow_empty_values: bool,
     required: bool,
     index: usize,
+    multiple: Option<Multiple>,
+    value_type: Option<ValueType>,
+    /// Environment variable used when the argument isn't passed
+    env: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -90,6 +603,10 @@ pub struct ClapOption {
     short: Option<char>,
     allow_empty_values: bool,
     required: bool,
+    multiple: Option<Multiple>,
+    value_type: Option<ValueType>,
+    /// Environment variable used when the argument isn't passed
+    env: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -97,14 +614,316 @@ pub struct ClapFlag {
     name: String,
     long: Option<String>,
     short: Option<char>,
+    /// Text to use instead of the flag when it is set
+    text: Option<String>,
+    /// Set by default and unset with `--no-<long>`
+    negatable: bool,
+    /// Environment variable used when the flag isn't passed
+    env: Option<String>,
+}
+
+/// Returns the source of `command`, which parses back to the same units
+///
+/// Text is escaped where needed, e.g. `#{` becomes `\#{`.
+pub fn to_source(command: &[Unit]) -> String {
+    let mut source = String::new();
+    write_units(&mut source, command, false).expect("writing to a String can't fail");
+    source
+}
+
+/// Writes the source of `units`, `nested` being whether they are in the body of a conditional
+fn write_units(f: &mut impl Write, units: &[Unit], nested: bool) -> fmt::Result {
+    units
+        .iter()
+        .try_for_each(|unit| unit.write_source(f, nested))
+}
+
+/// Writes `text` escaping `\`, `#{`, `{{` and, if `nested`, `}`
+fn write_text(f: &mut impl Write, text: &str, nested: bool) -> fmt::Result {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => f.write_str("\\\\")?,
+            '}' if nested => f.write_str("\\}")?,
+            '#' | '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                write!(f, "\\{c}{{")?;
+            }
+            _ => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes `text` escaping `\` and `}`, for the text of flags and regexes that run until `}`
+fn write_until_close(f: &mut impl Write, text: &str, escape_backslash: bool) -> fmt::Result {
+    for c in text.chars() {
+        match c {
+            '\\' if escape_backslash => f.write_str("\\\\")?,
+            '}' => f.write_str("\\}")?,
+            _ => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_source(f, false)
+    }
+}
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reference::Arg(name) => f.write_str(name),
+            Reference::Builtin(builtin) => write!(f, "@{builtin}"),
+            Reference::This => f.write_str("this"),
+        }
+    }
+}
+
+impl Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        if let Some(format) = &self.format {
+            write!(f, ":{format}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Unit {
+    /// Writes the source of this unit, `nested` being whether it is in the body of a conditional
+    fn write_source(&self, f: &mut impl Write, nested: bool) -> fmt::Result {
+        match self {
+            Unit::Text(text) => write_text(f, text, nested),
+            Unit::Builtin(builtin) => write!(f, "#{{@{builtin}}}"),
+            Unit::Positional(_) | Unit::Option(_) | Unit::Flag(_) => {
+                self.write_custom_arg(f)?;
+                f.write_char('}')
+            }
+            Unit::Conditional(conditional) => {
+                conditional.arg.write_custom_arg(f)?;
+                f.write_str(" && ")?;
+                write_units(f, &conditional.body, true)?;
+                f.write_char('}')
+            }
+            Unit::Expression(Expression::Value(reference, filters)) => {
+                write!(f, "{{{{{reference}")?;
+                for filter in filters {
+                    write!(f, " | {}", filter.name())?;
+                }
+                f.write_str("}}")
+            }
+            Unit::Expression(Expression::If(reference, then, otherwise)) => {
+                write!(f, "{{{{#if {reference}}}}}")?;
+                write_units(f, then, nested)?;
+                if !otherwise.is_empty() {
+                    f.write_str("{{else}}")?;
+                    write_units(f, otherwise, nested)?;
+                }
+                f.write_str("{{/if}}")
+            }
+            Unit::Expression(Expression::Each(reference, body)) => {
+                write!(f, "{{{{#each {reference}}}}}")?;
+                write_units(f, body, nested)?;
+                f.write_str("{{/each}}")
+            }
+        }
+    }
+
+    /// Writes a custom argument without the closing `}`
+    fn write_custom_arg(&self, f: &mut impl Write) -> fmt::Result {
+        let (long, short, env, value_type) = match self {
+            Unit::Positional(unit) => {
+                write!(f, "#{{{}:{}", unit.index, unit.name)?;
+                (None, None, &unit.env, &unit.value_type)
+            }
+            Unit::Option(unit) => {
+                f.write_str("#{")?;
+                (
+                    unit.long.as_deref(),
+                    unit.short,
+                    &unit.env,
+                    &unit.value_type,
+                )
+            }
+            Unit::Flag(unit) => {
+                f.write_str("#{")?;
+                if unit.negatable {
+                    f.write_str(NEGATABLE_PREFIX)?;
+                }
+                (unit.long.as_deref(), unit.short, &unit.env, &None)
+            }
+            _ => return Ok(()),
+        };
+        if let Some(long) = long {
+            f.write_str(long)?;
+        }
+        if let Some(short) = short {
+            write!(f, ",{short}")?;
+        }
+        if let Some(env) = env {
+            write!(f, "@{env}")?;
+        }
+        if let Some(name) = value_type.as_ref().and_then(ValueType::name) {
+            write!(f, ":{name}")?;
+        }
+        match self {
+            Unit::Positional(ClapPositional {
+                required,
+                allow_empty_values,
+                multiple,
+                ..
+            })
+            | Unit::Option(ClapOption {
+                required,
+                allow_empty_values,
+                multiple,
+                ..
+            }) => {
+                if *required {
+                    f.write_char('!')?;
+                }
+                if *allow_empty_values {
+                    f.write_char('*')?;
+                }
+                match multiple {
+                    Some(Multiple::Repeat) => f.write_char('+')?,
+                    Some(Multiple::Join(separator)) => write!(f, "+\"{separator}\"")?,
+                    None => (),
+                }
+            }
+            Unit::Flag(unit) => {
+                f.write_char('?')?;
+                if let Some(text) = &unit.text {
+                    f.write_char('=')?;
+                    write_until_close(f, text, true)?;
+                }
+            }
+            _ => (),
+        }
+        if let Some(ValueType::Regex(pattern)) = value_type {
+            f.write_char('~')?;
+            write_until_close(f, pattern, false)?;
+        }
+        Ok(())
+    }
+
+    /// Calls `f` with every custom argument in this unit, including the nested ones
+    pub fn visit_args_mut(&mut self, f: &mut impl FnMut(&mut Unit) -> Result<()>) -> Result<()> {
+        match self {
+            Unit::Positional(_) | Unit::Option(_) | Unit::Flag(_) => f(self),
+            Unit::Conditional(conditional) => {
+                conditional.arg.visit_args_mut(f)?;
+                conditional
+                    .body
+                    .iter_mut()
+                    .try_for_each(|unit| unit.visit_args_mut(f))
+            }
+            Unit::Expression(Expression::If(_, then, otherwise)) => then
+                .iter_mut()
+                .chain(otherwise)
+                .try_for_each(|unit| unit.visit_args_mut(f)),
+            Unit::Expression(Expression::Each(_, body)) => {
+                body.iter_mut().try_for_each(|unit| unit.visit_args_mut(f))
+            }
+            Unit::Text(_) | Unit::Builtin(_) | Unit::Expression(Expression::Value(..)) => Ok(()),
+        }
+    }
+
+    /// Renames the custom argument `from` to `to`, in declarations and expressions
+    ///
+    /// Options and flags named after their short version keep it.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let rename = |name: &mut String| {
+            if name == from {
+                *name = to.to_string();
+            }
+        };
+        match self {
+            Unit::Positional(unit) => rename(&mut unit.name),
+            Unit::Option(ClapOption { name, long, .. })
+            | Unit::Flag(ClapFlag { name, long, .. }) => {
+                if let Some(long) = long {
+                    rename(long);
+                    rename(name);
+                }
+            }
+            Unit::Conditional(conditional) => {
+                conditional.arg.rename(from, to);
+                conditional
+                    .body
+                    .iter_mut()
+                    .for_each(|unit| unit.rename(from, to));
+            }
+            Unit::Expression(expression) => {
+                let (reference, bodies) = match expression {
+                    Expression::Value(reference, _) => (reference, vec![]),
+                    Expression::If(reference, then, otherwise) => {
+                        (reference, vec![then, otherwise])
+                    }
+                    Expression::Each(reference, body) => (reference, vec![body]),
+                };
+                if let Reference::Arg(name) = reference {
+                    rename(name);
+                }
+                bodies
+                    .into_iter()
+                    .flatten()
+                    .for_each(|unit| unit.rename(from, to));
+            }
+            Unit::Text(_) | Unit::Builtin(_) => (),
+        }
+    }
 }
 
 pub fn parse(command: &str) -> Result<Command> {
     match_args(command)
-        .map(|(_, result)| result)
+        .and_then(|(_, result)| {
+            check_this(&result, false)?;
+            Ok(result)
+        })
         .context("failed parsing commands")
 }
 
+/// Checks that `{{this}}` is only used inside `{{#each}}`
+fn check_this(units: &[Unit], in_each: bool) -> Result<()> {
+    for unit in units {
+        let (reference, bodies): (_, Vec<&Command>) = match unit {
+            Unit::Expression(Expression::Value(reference, _)) => (Some(reference), vec![]),
+            Unit::Expression(Expression::If(reference, then, otherwise)) => {
+                (Some(reference), vec![then, otherwise])
+            }
+            Unit::Expression(Expression::Each(reference, body)) => {
+                ensure!(
+                    in_each || *reference != Reference::This,
+                    "`this` can only be used inside `{{{{#each}}}}`"
+                );
+                check_this(body, true)?;
+                continue;
+            }
+            Unit::Conditional(conditional) => (None, vec![&conditional.body]),
+            _ => continue,
+        };
+        ensure!(
+            in_each || reference != Some(&Reference::This),
+            "`this` can only be used inside `{{{{#each}}}}`"
+        );
+        for body in bodies {
+            check_this(body, in_each)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parses every element of an argv command
+pub fn parse_argv(args: &[String]) -> Result<Vec<Command>> {
+    ensure!(!args.is_empty(), "argv commands must not be empty");
+    args.iter().map(|arg| parse(arg)).collect()
+}
+
 fn match_literal(literal: &'static str) -> impl Fn(&str) -> Result<(&str, ())> {
     move |input: &str| {
         if let Some(matched) = input.strip_prefix(literal) {
@@ -121,13 +940,19 @@ fn match_literal(literal: &'static str) -> impl Fn(&str) -> Result<(&str, ())> {
 /// - '*'
 /// - ','
 /// - '}'
+/// - '&'
+/// - '+'
+/// - '='
+/// - ':'
+/// - '~'
+/// - '@'
 ///
 fn match_name(input: &str) -> (&str, String) {
     let mut name = String::new();
 
     for char in input.chars() {
         match char {
-            '!' | '?' | '*' | ',' | '}' => break,
+            '!' | '?' | '*' | ',' | '}' | '&' | '+' | '=' | ':' | '~' | '@' => break,
             _ => name.push(char),
         }
     }
@@ -140,14 +965,28 @@ fn match_symbol(input: &str) -> Result<(&str, char), &str> {
         Some('!') => Ok((&input['!'.len_utf8()..], '!')),
         Some('?') => Ok((&input['?'.len_utf8()..], '?')),
         Some('*') => Ok((&input['*'.len_utf8()..], '*')),
+        Some('+') => Ok((&input['+'.len_utf8()..], '+')),
         _ => Err(input),
     }
 }
 
-/// Returns the input until: `#{`, `{{` or `\`
-fn match_until_custom_arg_start(input: &str) -> (&str, &str) {
-    for (index, c) in input.chars().enumerate() {
-        if c == '\\' || ((c == '#' || c == '{') && input.get(index + 1..index + 2) == Some("{")) {
+/// Matches an optional separator in double quotes
+/// Example: `","`
+fn match_separator(input: &str) -> Result<(&str, Option<String>)> {
+    let Some(next) = input.strip_prefix('"') else {
+        return Ok((input, None));
+    };
+    let end = next.find('"').context("expected literal \"")?;
+    Ok((&next[end + 1..], Some(next[..end].to_string())))
+}
+
+/// Returns the input until: `#{`, `{{`, `\` or, if `nested`, `}`
+fn match_until_custom_arg_start(input: &str, nested: bool) -> (&str, &str) {
+    for (index, c) in input.char_indices() {
+        if c == '\\'
+            || (nested && c == '}')
+            || ((c == '#' || c == '{') && input.get(index + 1..index + 2) == Some("{"))
+        {
             return (&input[index..], &input[..index]);
         }
     }
@@ -156,7 +995,7 @@ fn match_until_custom_arg_start(input: &str) -> (&str, &str) {
 
 fn match_usize(input: &str) -> Result<(&str, usize)> {
     let mut number_str = String::new();
-    for (index, c) in input.chars().enumerate() {
+    for (index, c) in input.char_indices() {
         if c.is_numeric() {
             number_str.push(c);
         } else if number_str.is_empty() {
@@ -179,32 +1018,150 @@ fn match_num(input: &str) -> Result<(&str, usize)> {
     Ok((next, num))
 }
 
-fn match_custom_arg(input: &str) -> Result<(&str, Unit)> {
+/// Matches `@<variable>`, the environment variable of a custom argument
+/// Example: `@GIT_AUTHOR_NAME`
+fn match_env(input: &str) -> Result<(&str, String)> {
+    let (next, _) = match_literal("@")(input)?;
+    let end = next
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(next.len());
+    ensure!(
+        end > 0,
+        "expected the name of an environment variable after `@`"
+    );
+    Ok((&next[end..], next[..end].to_string()))
+}
+
+/// Matches `:<type>`
+/// Example: `:int`
+fn match_type(input: &str) -> Result<(&str, ValueType)> {
+    let (next, _) = match_literal(":")(input)?;
+    let end = next
+        .find(|c: char| !c.is_ascii_alphanumeric())
+        .unwrap_or(next.len());
+    Ok((&next[end..], ValueType::from_name(&next[..end])?))
+}
+
+/// Matches `~<regex>` until an unescaped `}`
+/// Example: `~^[a-z]+$`
+fn match_pattern(input: &str) -> Result<(&str, ValueType)> {
+    let (mut next, _) = match_literal("~")(input)?;
+    let mut pattern = String::new();
+    loop {
+        match next.chars().next() {
+            Some('}') | None => break,
+            Some('\\') if next.starts_with("\\}") => {
+                pattern.push('}');
+                next = &next[2..];
+            }
+            Some(c) => {
+                pattern.push(c);
+                next = &next[c.len_utf8()..];
+            }
+        }
+    }
+    Regex::new(&pattern).with_context(|| format!("invalid regex `{pattern}`"))?;
+    Ok((next, ValueType::Regex(pattern)))
+}
+
+/// Matches `@<name>[:<format>]}`
+/// Example: `@project}` or `@date:%Y}`
+fn match_builtin(input: &str) -> Result<(&str, Unit)> {
+    let (next, _) = match_literal("@")(input)?;
+    let (next, name) = match_name(next);
+    let (next, format) = match next.strip_prefix(':') {
+        Some(format) => {
+            let end = format.find('}').unwrap_or(format.len());
+            (&format[end..], Some(format[..end].to_string()))
+        }
+        None => (next, None),
+    };
+    let (next, _) = match_literal("}")(next)?;
+    builtins::validate(&name, format.as_deref())?;
+    Ok((next, Unit::Builtin(Builtin { name, format })))
+}
+
+/// Matches `=<text>` until an unescaped `}`, where the text is what a flag expands to
+/// Example: `=-vvv`
+fn match_flag_text(input: &str) -> Result<(&str, String)> {
+    let (mut next, _) = match_literal("=")(input)?;
+    let mut text = String::new();
+    loop {
+        match next.chars().next() {
+            Some('}') | None => return Ok((next, text)),
+            Some('\\') if next.starts_with("\\}") || next.starts_with("\\\\") => {
+                text.push_str(&next[1..2]);
+                next = &next[2..];
+            }
+            Some(c) => {
+                text.push(c);
+                next = &next[c.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// Matches ` && <units>}`, where the units are the body of a conditional
+/// Example: ` && docker build .}`
+fn match_conditional_body(input: &str) -> Result<(&str, Command)> {
+    let (next, _) = match_literal("&&")(input.trim_start())?;
+    let mut next = next.trim_start();
+    let mut body = Vec::new();
+    loop {
+        if let Ok((next, _)) = match_literal("}")(next) {
+            return Ok((next, body));
+        }
+        ensure!(!next.is_empty(), "expected literal }}");
+        let (inner_next, unit) = match_unit(next, true)?;
+        body.push(unit);
+        next = inner_next;
+    }
+}
+
+/// Matches a custom argument, `nested` being whether it is in the body of a conditional
+fn match_custom_arg(input: &str, nested: bool) -> Result<(&str, Unit)> {
     if let Ok((next, _)) = match_literal("\\#{")(input) {
-        let (next, text) = match_until_custom_arg_start(next);
+        let (next, text) = match_until_custom_arg_start(next, nested);
         Ok((next, Unit::Text(format!("#{{{}", text))))
+    } else if input.starts_with("#{@") {
+        match_builtin(&input["#{".len()..])
     } else {
         let (next, _) = match_literal("#{")(input)?;
         let (next, index) = match match_num(next) {
             Ok((next, index)) => (next, Some(index)),
             Err(_) => (next, None),
         };
-        let (next, long) = match_name(next);
+        let (next, mut long) = match_name(next);
         let (next, _) = match_literal(",")(next).unwrap_or((next, ()));
-        let (mut next, short) = match_name(next);
+        let (next, mut short) = match_name(next);
+        let (next, env) = if next.starts_with('@') {
+            let (next, env) = match_env(next)?;
+            (next, Some(env))
+        } else {
+            (next, None)
+        };
+        let (mut next, mut value_type) = if next.starts_with(':') {
+            let (next, value_type) = match_type(next)?;
+            (next, Some(value_type))
+        } else {
+            (next, None)
+        };
 
         if index.is_some() && !short.is_empty() {
             bail!("short not allowed in positional arguments");
         }
 
-        let name = if long.is_empty() {
-            short.clone()
-        } else {
-            long.clone()
-        };
         let mut allow_empty_values = false;
         let mut required = false;
         let mut flag = false;
+        let mut multiple = long.strip_suffix("...").map(|_| Multiple::Repeat);
+        if multiple.is_some() {
+            long.truncate(long.len() - "...".len());
+        }
+        let negatable = long.starts_with(NEGATABLE_PREFIX);
+        if negatable {
+            long.replace_range(..NEGATABLE_PREFIX.len(), "");
+        }
 
         while let Ok((n, symbol)) = match_symbol(next) {
             match symbol {
@@ -216,31 +1173,92 @@ fn match_custom_arg(input: &str) -> Result<(&str, Unit)> {
                     ensure!(!required, "incompatible symbols: `?` and `!`");
                     ensure!(!allow_empty_values, "incompatible symbols: `?` and `*`");
                     ensure!(index.is_none(), "incompatible symbols: `?` and `<num>:`");
+                    ensure!(multiple.is_none(), "incompatible symbols: `?` and `+`");
                     flag = true;
                 }
                 '*' => {
                     ensure!(!flag, "incompatible symbols: `*` and `?`");
                     allow_empty_values = true;
                 }
+                '+' => {
+                    ensure!(!flag, "incompatible symbols: `+` and `?`");
+                    let (n, separator) = match_separator(n)?;
+                    multiple = Some(separator.map_or(Multiple::Repeat, Multiple::Join));
+                    next = n;
+                    continue;
+                }
                 _ => unreachable!(),
             };
             next = n;
         }
 
-        let (next, _) = match_literal("}")(next)?;
+        let next = if next.starts_with('~') {
+            ensure!(value_type.is_none(), "`~` can't be used with a type");
+            let (next, pattern) = match_pattern(next)?;
+            value_type = Some(pattern);
+            next
+        } else {
+            next
+        };
+        ensure!(
+            !flag || value_type.is_none(),
+            "types are only allowed in options and positional arguments"
+        );
+
+        let (next, text) = match match_flag_text(next) {
+            Ok((next, text)) => {
+                ensure!(flag, "`=` is only allowed in flags");
+                (next, Some(text))
+            }
+            Err(_) => (next, None),
+        };
+        ensure!(!negatable || flag, "`[no-]` is only allowed in flags");
+        ensure!(
+            !negatable || !long.trim_end().is_empty(),
+            "`[no-]` needs a long name"
+        );
+        ensure!(
+            !negatable || short.trim().is_empty(),
+            "`[no-]` flags can't have a short version, since it would unset them"
+        );
+
+        let (next, body) = if next.trim_start().starts_with("&&") {
+            let (next, body) = match_conditional_body(next)?;
+            // Allow `#{name && ...}`
+            long.truncate(long.trim_end().len());
+            short.truncate(short.trim_end().len());
+            (next, Some(body))
+        } else {
+            (match_literal("}")(next)?.0, None)
+        };
 
+        let name = if long.is_empty() {
+            short.clone()
+        } else {
+            long.clone()
+        };
         let long = if long.is_empty() { None } else { Some(long) };
         let short = short.chars().next();
 
         let arg = {
             if flag {
-                Unit::Flag(ClapFlag { name, long, short })
+                Unit::Flag(ClapFlag {
+                    name,
+                    long,
+                    short,
+                    text,
+                    negatable,
+                    env,
+                })
             } else if let Some(index) = index {
                 Unit::Positional(ClapPositional {
                     name,
                     allow_empty_values,
                     required,
                     index,
+                    multiple,
+                    value_type,
+                    env,
                 })
             } else {
                 Unit::Option(ClapOption {
@@ -249,22 +1267,129 @@ fn match_custom_arg(input: &str) -> Result<(&str, Unit)> {
                     short,
                     allow_empty_values,
                     required,
+                    multiple,
+                    value_type,
+                    env,
                 })
             }
         };
 
+        let arg = match body {
+            Some(body) => Unit::Conditional(Conditional {
+                arg: Box::new(arg),
+                body,
+            }),
+            None => arg,
+        };
+
         Ok((next, arg))
     }
 }
 
-fn match_unit(input: &str) -> Result<(&str, Unit)> {
-    let (next, text) = match_until_custom_arg_start(input);
+/// Matches `{{<content>}}`, returning the content without surrounding whitespace
+/// Example: `{{ name | upper }}`
+fn match_tag(input: &str) -> Result<(&str, &str)> {
+    let (next, _) = match_literal("{{")(input)?;
+    let end = next.find("}}").context("expected literal }}")?;
+    Ok((&next[end + "}}".len()..], next[..end].trim()))
+}
+
+/// Matches the name of a custom argument, `@<builtin>[:<format>]` or `this`
+fn match_reference(input: &str) -> Result<Reference> {
+    if input == "this" {
+        return Ok(Reference::This);
+    }
+    if let Some(builtin) = input.strip_prefix('@') {
+        let (name, format) = match builtin.split_once(':') {
+            Some((name, format)) => (name, Some(format.to_string())),
+            None => (builtin, None),
+        };
+        builtins::validate(name, format.as_deref())?;
+        return Ok(Reference::Builtin(Builtin {
+            name: name.to_string(),
+            format,
+        }));
+    }
+    ensure!(
+        !input.is_empty()
+            && input
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-'),
+        "invalid name `{input}` in expression"
+    );
+    Ok(Reference::Arg(input.to_string()))
+}
+
+/// Matches units until one of the tags in `ends`, returning the units and the tag
+/// Example: `lib{{/if}}`
+fn match_block<'a>(
+    input: &'a str,
+    nested: bool,
+    ends: &[&'static str],
+) -> Result<(&'a str, Command, &'static str)> {
+    let mut next = input;
+    let mut body = Vec::new();
+    loop {
+        if let Ok((inner_next, content)) = match_tag(next) {
+            if let Some(end) = ends.iter().find(|end| **end == content) {
+                return Ok((inner_next, body, end));
+            }
+        }
+        if next.is_empty() || (nested && next.starts_with('}')) {
+            bail!("expected `{{{{{}}}}}`", ends[ends.len() - 1]);
+        }
+        let (inner_next, unit) = match_unit(next, nested)?;
+        body.push(unit);
+        next = inner_next;
+    }
+}
+
+/// Matches an expression, `nested` being whether it is in the body of a conditional
+/// Example: `{{name | upper}}` or `{{#if lib}}--lib{{/if}}`
+fn match_expression(input: &str, nested: bool) -> Result<(&str, Unit)> {
+    let (next, content) = match_tag(input)?;
+    let (next, expression) = if let Some(reference) = content.strip_prefix("#if ") {
+        let reference = match_reference(reference.trim())?;
+        let (next, then, end) = match_block(next, nested, &["else", "/if"])?;
+        let (next, otherwise) = match end {
+            "else" => {
+                let (next, otherwise, _) = match_block(next, nested, &["/if"])?;
+                (next, otherwise)
+            }
+            _ => (next, Vec::new()),
+        };
+        (next, Expression::If(reference, then, otherwise))
+    } else if let Some(reference) = content.strip_prefix("#each ") {
+        let reference = match_reference(reference.trim())?;
+        let (next, body, _) = match_block(next, nested, &["/each"])?;
+        (next, Expression::Each(reference, body))
+    } else if content.starts_with(['#', '/']) || content == "else" {
+        bail!("unexpected `{{{{{content}}}}}`")
+    } else {
+        let mut parts = content.split('|').map(str::trim);
+        let reference = match_reference(parts.next().unwrap_or_default())?;
+        let filters = parts.map(Filter::from_name).collect::<Result<_>>()?;
+        (next, Expression::Value(reference, filters))
+    };
+    Ok((next, Unit::Expression(expression)))
+}
+
+/// Matches a unit, `nested` being whether it is in the body of a conditional
+fn match_unit(input: &str, nested: bool) -> Result<(&str, Unit)> {
+    let (next, text) = match_until_custom_arg_start(input, nested);
 
     if text.is_empty() {
         if let Ok((next, _)) = match_literal("\\\\")(next) {
             Ok((next, Unit::Text("\\".to_string())))
+        } else if let Some(next) = next.strip_prefix("\\}").filter(|_| nested) {
+            Ok((next, Unit::Text("}".to_string())))
+        } else if let Some(next) = next.strip_prefix("\\{{") {
+            let (next, text) = match_until_custom_arg_start(next, nested);
+            Ok((next, Unit::Text(format!("{{{{{text}"))))
+        } else if next.starts_with("{{") {
+            match_expression(next, nested)
         } else {
-            match_custom_arg(next)
+            match_custom_arg(next, nested)
         }
     } else {
         let unit = Unit::Text(text.to_string());
@@ -276,7 +1401,7 @@ fn match_args(input: &str) -> Result<((), Command)> {
     let mut result = Vec::new();
     let mut next = input;
     while !next.is_empty() {
-        let (inner_next, unit) = match_unit(next)?;
+        let (inner_next, unit) = match_unit(next, false)?;
         result.push(unit);
         next = inner_next;
     }
@@ -289,13 +1414,13 @@ mod tests {
 
     #[test]
     fn test_match_unit_error() {
-        assert!(match_unit("").is_err());
+        assert!(match_unit("", false).is_err());
         assert_eq!(
-            match_unit("\\hello").map_err(|err| err.to_string()),
+            match_unit("\\hello", false).map_err(|err| err.to_string()),
             Err("expected literal #{".to_string())
         );
         assert_eq!(
-            match_unit("\\").map_err(|err| err.to_string()),
+            match_unit("\\", false).map_err(|err| err.to_string()),
             Err("expected literal #{".to_string())
         );
     }
@@ -303,15 +1428,15 @@ mod tests {
     #[test]
     fn test_match_unit() {
         assert_eq!(
-            match_unit("hello").unwrap(),
+            match_unit("hello", false).unwrap(),
             ("", Unit::Text("hello".to_string()))
         );
         assert_eq!(
-            match_unit("hello world").unwrap(),
+            match_unit("hello world", false).unwrap(),
             ("", Unit::Text("hello world".to_string()))
         );
         assert_eq!(
-            match_unit("#{hello}").unwrap(),
+            match_unit("#{hello}", false).unwrap(),
             (
                 "",
                 Unit::Option(ClapOption {
@@ -319,20 +1444,23 @@ mod tests {
                     long: Some("hello".to_string()),
                     short: None,
                     name: "hello".to_string(),
-                    required: false
+                    required: false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 })
             )
         );
         assert_eq!(
-            match_unit("\\\\").unwrap(),
+            match_unit("\\\\", false).unwrap(),
             ("", Unit::Text("\\".to_string()))
         );
         assert_eq!(
-            match_unit("\\#{hello}").unwrap(),
+            match_unit("\\#{hello}", false).unwrap(),
             ("", Unit::Text("#{hello}".to_string()))
         );
         assert_eq!(
-            match_unit("hello #{world}").unwrap(),
+            match_unit("hello #{world}", false).unwrap(),
             ("#{world}", Unit::Text("hello ".to_string()))
         );
     }
@@ -347,6 +1475,9 @@ mod tests {
                     name: "lib".to_string(),
                     long: Some("lib".to_string()),
                     short: None,
+                    text: None,
+                    negatable: false,
+                    env: None,
                 })
             ]
         );
@@ -359,7 +1490,10 @@ mod tests {
                     long:Some("one".to_string()),
                     short:None,
                     allow_empty_values: false,
-                    required: false
+                    required: false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" two ".to_string()),
                 Unit::Option(ClapOption {
@@ -367,7 +1501,10 @@ mod tests {
                     long:Some("two".to_string()),
                     short:None,
                     allow_empty_values: false,
-                    required: true
+                    required: true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" three ".to_string()),
                 Unit::Option(ClapOption {
@@ -375,7 +1512,10 @@ mod tests {
                     long:Some("three".to_string()),
                     short:None,
                     allow_empty_values: true,
-                    required: false
+                    required: false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" four ".to_string()),
                 Unit::Option(ClapOption {
@@ -383,7 +1523,10 @@ mod tests {
                     long:Some("four".to_string()),
                     short:None,
                     allow_empty_values: true,
-                    required: true
+                    required: true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" five ".to_string()),
                 Unit::Option(ClapOption {
@@ -391,48 +1534,69 @@ mod tests {
                     long:Some("five".to_string()),
                     short:None,
                     allow_empty_values: true,
-                    required: true
+                    required: true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" six ".to_string()),
                 Unit::Flag(ClapFlag {
                     name:"six".to_string(),
                     long:Some("six".to_string()),
                     short:None,
+                    text: None,
+                    negatable: false,
+                    env: None,
                 }),
                 Unit::Text(" seven ".to_string()),
                 Unit::Positional(ClapPositional {
                     name:"seven".to_string(),
                     allow_empty_values:false,
                     required:true,
-                    index: 1
+                    index: 1,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" eight ".to_string()),
                 Unit::Positional(ClapPositional {
                     name:"eight".to_string(),
                     allow_empty_values:true,
                     required:true,
-                    index: 2
+                    index: 2,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" nine ".to_string()),
                 Unit::Positional(ClapPositional {
                     name:"nine".to_string(),
                     allow_empty_values:true,
                     required:true,
-                    index: 3
+                    index: 3,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" ten ".to_string()),
                 Unit::Positional(ClapPositional {
                     name:"ten".to_string(),
                     allow_empty_values:false,
                     required:false,
-                    index: 4
+                    index: 4,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" eleven ".to_string()),
                 Unit::Positional(ClapPositional {
                     name:"eleven".to_string(),
                     allow_empty_values:true,
                     required:false,
-                    index: 5
+                    index: 5,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" twelve ".to_string()),
                 Unit::Option(ClapOption {
@@ -441,6 +1605,9 @@ mod tests {
                     short: Some('t'),
                     allow_empty_values:false,
                     required:false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" thirteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -449,6 +1616,9 @@ mod tests {
                     short: Some('h'),
                     allow_empty_values:false,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" fourteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -457,6 +1627,9 @@ mod tests {
                     short: Some('f'),
                     allow_empty_values:true,
                     required:false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" fifteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -465,6 +1638,9 @@ mod tests {
                     short: Some('i'),
                     allow_empty_values:true,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" sixteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -473,12 +1649,18 @@ mod tests {
                     short: Some('s'),
                     allow_empty_values:true,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" seventeen ".to_string()),
                 Unit::Flag(ClapFlag {
                     name:"seventeen".to_string(),
                     long: Some("seventeen".to_string()),
                     short: Some('e'),
+                    text: None,
+                    negatable: false,
+                    env: None,
                 }),
                 Unit::Text(" eighteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -487,6 +1669,9 @@ mod tests {
                     short: Some('g'),
                     allow_empty_values:false,
                     required:false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" nineteen ".to_string()),
                 Unit::Option(ClapOption {
@@ -495,6 +1680,9 @@ mod tests {
                     short: Some('n'),
                     allow_empty_values:false,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" twenty ".to_string()),
                 Unit::Option(ClapOption {
@@ -503,6 +1691,9 @@ mod tests {
                     short: Some('w'),
                     allow_empty_values:true,
                     required:false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" twenty-one ".to_string()),
                 Unit::Option(ClapOption {
@@ -511,6 +1702,9 @@ mod tests {
                     short: Some('y'),
                     allow_empty_values:true,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" twenty-two ".to_string()),
                 Unit::Option(ClapOption {
@@ -519,12 +1713,18 @@ mod tests {
                     short: Some('o'),
                     allow_empty_values:true,
                     required:true,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" twenty-three ".to_string()),
                 Unit::Flag(ClapFlag {
                     name:"x".to_string(),
                     long: None,
                     short: Some('x'),
+                    text: None,
+                    negatable: false,
+                    env: None,
                 }),
             ]
         );
@@ -549,7 +1749,7 @@ mod tests {
     #[test]
     fn test_match_simple_args_multi_line() {
         assert_eq!(
-            match_until_custom_arg_start("echo one\necho two\necho three"),
+            match_until_custom_arg_start("echo one\necho two\necho three", false),
             ("", "echo one\necho two\necho three")
         )
     }
@@ -564,14 +1764,20 @@ mod tests {
                     name: "first".to_string(),
                     required: true,
                     allow_empty_values: false,
-                    index: 1
+                    index: 1,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(" and my last name is ".to_string()),
                 Unit::Positional(ClapPositional {
                     name: "last".to_string(),
                     required: true,
                     allow_empty_values: false,
-                    index: 2
+                    index: 2,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 }),
                 Unit::Text(".".to_string()),
             ]
@@ -581,53 +1787,59 @@ mod tests {
     #[test]
     fn test_match_simple_args() {
         assert_eq!(
-            match_until_custom_arg_start("hello world"),
+            match_until_custom_arg_start("hello world", false),
             ("", "hello world")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello #{world."),
+            match_until_custom_arg_start("hello #{world.", false),
             ("#{world.", "hello ")
         );
-        assert_eq!(match_until_custom_arg_start("#{hello"), ("#{hello", ""));
         assert_eq!(
-            match_until_custom_arg_start("hello #world"),
+            match_until_custom_arg_start("#{hello", false),
+            ("#{hello", "")
+        );
+        assert_eq!(
+            match_until_custom_arg_start("hello #world", false),
             ("", "hello #world")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello {world"),
+            match_until_custom_arg_start("hello {world", false),
             ("", "hello {world")
         );
-        assert_eq!(match_until_custom_arg_start("{{hello"), ("{{hello", ""));
         assert_eq!(
-            match_until_custom_arg_start("{{hello world"),
+            match_until_custom_arg_start("{{hello", false),
+            ("{{hello", "")
+        );
+        assert_eq!(
+            match_until_custom_arg_start("{{hello world", false),
             ("{{hello world", "")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello {{world"),
+            match_until_custom_arg_start("hello {{world", false),
             ("{{world", "hello ")
         );
         assert_eq!(
-            match_until_custom_arg_start("{hello world"),
+            match_until_custom_arg_start("{hello world", false),
             ("", "{hello world")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello \\world"),
+            match_until_custom_arg_start("hello \\world", false),
             ("\\world", "hello ")
         );
         assert_eq!(
-            match_until_custom_arg_start("\\#{hello}"),
+            match_until_custom_arg_start("\\#{hello}", false),
             ("\\#{hello}", "")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello \\#{world}"),
+            match_until_custom_arg_start("hello \\#{world}", false),
             ("\\#{world}", "hello ")
         );
         assert_eq!(
-            match_until_custom_arg_start("\\{{hello}}"),
+            match_until_custom_arg_start("\\{{hello}}", false),
             ("\\{{hello}}", "")
         );
         assert_eq!(
-            match_until_custom_arg_start("hello \\{{world}}"),
+            match_until_custom_arg_start("hello \\{{world}}", false),
             ("\\{{world}}", "hello ")
         );
     }
@@ -635,7 +1847,7 @@ mod tests {
     #[test]
     fn test_match_custom_arg() {
         assert_eq!(
-            match_custom_arg("#{color}").unwrap(),
+            match_custom_arg("#{color}", false).unwrap(),
             (
                 "",
                 Unit::Option(ClapOption {
@@ -643,20 +1855,23 @@ mod tests {
                     long: Some("color".to_string()),
                     name: "color".to_string(),
                     short: None,
-                    required: false
+                    required: false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
                 })
             )
         );
         assert_eq!(
-            match_custom_arg("\\#{color}").unwrap(),
+            match_custom_arg("\\#{color}", false).unwrap(),
             ("", Unit::Text("#{color}".to_string()))
         );
         assert_eq!(
-            match_custom_arg("\\#{hello} world").unwrap(),
+            match_custom_arg("\\#{hello} world", false).unwrap(),
             ("", Unit::Text("#{hello} world".to_string()))
         );
         assert_eq!(
-            match_custom_arg("\\#{hello} #{world}").unwrap(),
+            match_custom_arg("\\#{hello} #{world}", false).unwrap(),
             ("#{world}", Unit::Text("#{hello} ".to_string()))
         );
     }
@@ -664,7 +1879,7 @@ mod tests {
     #[test]
     fn test_match_custom_arg_starting_with_number() {
         assert_eq!(
-            match_custom_arg("#{1color}").unwrap(),
+            match_custom_arg("#{1color}", false).unwrap(),
             (
                 "",
                 Unit::Option(ClapOption {
@@ -672,10 +1887,423 @@ mod tests {
                     long: Some("1color".to_string()),
                     name: "1color".to_string(),
                     short: None,
-                    required: false
+                    required: false,
+                    multiple: None,
+                    value_type: None,
+                    env: None,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn test_match_custom_arg_builtin() {
+        assert_eq!(
+            match_custom_arg("#{@project} world", false).unwrap(),
+            (" world", Unit::Builtin(Builtin::new("project")))
+        );
+        assert_eq!(
+            match_custom_arg("#{@unknown}", false).map_err(|err| err.to_string()),
+            Err("unknown built-in variable `@unknown`".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{@date:%Y/%m}/", false).unwrap(),
+            (
+                "/",
+                Unit::Builtin(Builtin {
+                    name: "date".to_string(),
+                    format: Some("%Y/%m".to_string())
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn test_match_custom_arg_multiple() {
+        assert_eq!(
+            match_custom_arg("#{feature,f+}", false).unwrap(),
+            (
+                "",
+                Unit::Option(ClapOption {
+                    name: "feature".to_string(),
+                    long: Some("feature".to_string()),
+                    short: Some('f'),
+                    allow_empty_values: false,
+                    required: false,
+                    multiple: Some(Multiple::Repeat),
+                    value_type: None,
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{feature+\",\"!}", false).unwrap(),
+            (
+                "",
+                Unit::Option(ClapOption {
+                    name: "feature".to_string(),
+                    long: Some("feature".to_string()),
+                    short: None,
+                    allow_empty_values: false,
+                    required: true,
+                    multiple: Some(Multiple::Join(",".to_string())),
+                    value_type: None,
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{2:files...}", false).unwrap(),
+            (
+                "",
+                Unit::Positional(ClapPositional {
+                    name: "files".to_string(),
+                    allow_empty_values: false,
+                    required: false,
+                    index: 2,
+                    multiple: Some(Multiple::Repeat),
+                    value_type: None,
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{feature+\",}", false).map_err(|err| err.to_string()),
+            Err("expected literal \"".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{feature?+}", false).map_err(|err| err.to_string()),
+            Err("incompatible symbols: `+` and `?`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_custom_arg_flag_text() {
+        assert_eq!(
+            match_custom_arg("#{verbose,v?=-v -v \\}} after", false).unwrap(),
+            (
+                " after",
+                Unit::Flag(ClapFlag {
+                    name: "verbose".to_string(),
+                    long: Some("verbose".to_string()),
+                    short: Some('v'),
+                    text: Some("-v -v }".to_string()),
+                    negatable: false,
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{[no-]git?}", false).unwrap(),
+            (
+                "",
+                Unit::Flag(ClapFlag {
+                    name: "git".to_string(),
+                    long: Some("git".to_string()),
+                    short: None,
+                    text: None,
+                    negatable: true,
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{[no-]git,G?}", false).map_err(|err| err.to_string()),
+            Err("`[no-]` flags can't have a short version, since it would unset them".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{verbose=-vvv}", false).map_err(|err| err.to_string()),
+            Err("`=` is only allowed in flags".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{[no-]git}", false).map_err(|err| err.to_string()),
+            Err("`[no-]` is only allowed in flags".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{[no-],g?}", false).map_err(|err| err.to_string()),
+            Err("`[no-]` needs a long name".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_custom_arg_type() {
+        assert_eq!(
+            match_custom_arg("#{port,p:int!}", false).unwrap(),
+            (
+                "",
+                Unit::Option(ClapOption {
+                    name: "port".to_string(),
+                    long: Some("port".to_string()),
+                    short: Some('p'),
+                    allow_empty_values: false,
+                    required: true,
+                    multiple: None,
+                    value_type: Some(ValueType::Int),
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{1:name!~^[a-z]{2,\\}$}", false).unwrap(),
+            (
+                "",
+                Unit::Positional(ClapPositional {
+                    name: "name".to_string(),
+                    allow_empty_values: false,
+                    required: true,
+                    index: 1,
+                    multiple: None,
+                    value_type: Some(ValueType::Regex("^[a-z]{2,}$".to_string())),
+                    env: None,
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{port:float}", false).map_err(|err| err.to_string()),
+            Err("unknown type `float`, expected one of: int, bool, path, url, semver".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{name~[}", false).map_err(|err| err.to_string()),
+            Err("invalid regex `[`".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{port:int~[0-9]}", false).map_err(|err| err.to_string()),
+            Err("`~` can't be used with a type".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{lib:bool?}", false).map_err(|err| err.to_string()),
+            Err("types are only allowed in options and positional arguments".to_string())
+        );
+    }
+
+    #[test]
+    fn test_typed_values_empty() {
+        let units = parse("#{port:int*} #{version:semver} #{url:url*}").unwrap();
+        let cmd = clap::Command::new("")
+            .no_binary_name(true)
+            .args(Unit::to_clap_args(units.iter().collect()));
+        let matches = cmd
+            .clone()
+            .try_get_matches_from(["--port", "", "--url", ""])
+            .unwrap();
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "");
+        assert_eq!(matches.get_one::<String>("url").unwrap(), "");
+        let error = cmd.try_get_matches_from(["--version", ""]).unwrap_err();
+        assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn test_match_custom_arg_env() {
+        assert_eq!(
+            match_custom_arg("#{author,a@GIT_AUTHOR_NAME!}", false).unwrap(),
+            (
+                "",
+                Unit::Option(ClapOption {
+                    name: "author".to_string(),
+                    long: Some("author".to_string()),
+                    short: Some('a'),
+                    allow_empty_values: false,
+                    required: true,
+                    multiple: None,
+                    value_type: None,
+                    env: Some("GIT_AUTHOR_NAME".to_string()),
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{ci@CI?}", false).unwrap(),
+            (
+                "",
+                Unit::Flag(ClapFlag {
+                    name: "ci".to_string(),
+                    long: Some("ci".to_string()),
+                    short: None,
+                    text: None,
+                    negatable: false,
+                    env: Some("CI".to_string()),
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{author@}", false).map_err(|err| err.to_string()),
+            Err("expected the name of an environment variable after `@`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_expression() {
+        assert_eq!(
+            match_unit("{{ name | snake | upper }} after", false).unwrap(),
+            (
+                " after",
+                Unit::Expression(Expression::Value(
+                    Reference::Arg("name".to_string()),
+                    vec![Filter::Snake, Filter::Upper]
+                ))
+            )
+        );
+        assert_eq!(
+            match_unit("{{@date:%Y}}", false).unwrap(),
+            (
+                "",
+                Unit::Expression(Expression::Value(
+                    Reference::Builtin(Builtin {
+                        name: "date".to_string(),
+                        format: Some("%Y".to_string())
+                    }),
+                    vec![]
+                ))
+            )
+        );
+        assert_eq!(
+            match_unit("{{#if lib}}--lib{{else}}--bin {{@project}}{{/if}}", false).unwrap(),
+            (
+                "",
+                Unit::Expression(Expression::If(
+                    Reference::Arg("lib".to_string()),
+                    vec![Unit::Text("--lib".to_string())],
+                    vec![
+                        Unit::Text("--bin ".to_string()),
+                        Unit::Expression(Expression::Value(
+                            Reference::Builtin(Builtin::new("project")),
+                            vec![]
+                        ))
+                    ]
+                ))
+            )
+        );
+        assert_eq!(
+            match_unit("{{#each crates}} -p {{this}}{{/each}}", false).unwrap(),
+            (
+                "",
+                Unit::Expression(Expression::Each(
+                    Reference::Arg("crates".to_string()),
+                    vec![
+                        Unit::Text(" -p ".to_string()),
+                        Unit::Expression(Expression::Value(Reference::This, vec![]))
+                    ]
+                ))
+            )
+        );
+        assert_eq!(
+            match_unit("\\{{name}}", false).unwrap(),
+            ("", Unit::Text("{{name}}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_match_expression_error() {
+        let error = |input| match_unit(input, false).map_err(|err| err.to_string());
+        assert_eq!(error("{{name"), Err("expected literal }}".to_string()));
+        assert_eq!(
+            error("{{#if lib}}--lib"),
+            Err("expected `{{/if}}`".to_string())
+        );
+        assert_eq!(error("{{/if}}"), Err("unexpected `{{/if}}`".to_string()));
+        assert_eq!(
+            error("{{name | reverse}}"),
+            Err(
+                "unknown filter `reverse`, expected one of: upper, lower, snake, kebab, camel, \
+                 pascal, trim, quote"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            error("{{a b}}"),
+            Err("invalid name `a b` in expression".to_string())
+        );
+        assert_eq!(
+            parse("{{#if lib}}{{this}}{{/if}}").map_err(|err| format!("{err:#}")),
+            Err("failed parsing commands: `this` can only be used inside `{{#each}}`".to_string())
+        );
+        assert_eq!(
+            match_custom_arg("#{docker? && {{#if tag}}:{{tag}}}", false)
+                .map_err(|err| err.to_string()),
+            Err("expected `{{/if}}`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_custom_arg_conditional() {
+        assert_eq!(
+            match_custom_arg("#{docker? && docker build #{tag,t} \\} .} after", false).unwrap(),
+            (
+                " after",
+                Unit::Conditional(Conditional {
+                    arg: Box::new(Unit::Flag(ClapFlag {
+                        name: "docker".to_string(),
+                        long: Some("docker".to_string()),
+                        short: None,
+                        text: None,
+                        negatable: false,
+                        env: None,
+                    })),
+                    body: vec![
+                        Unit::Text("docker build ".to_string()),
+                        Unit::Option(ClapOption {
+                            name: "tag".to_string(),
+                            long: Some("tag".to_string()),
+                            short: Some('t'),
+                            allow_empty_values: false,
+                            required: false,
+                            multiple: None,
+                            value_type: None,
+                            env: None,
+                        }),
+                        Unit::Text(" ".to_string()),
+                        Unit::Text("}".to_string()),
+                        Unit::Text(" .".to_string()),
+                    ]
+                })
+            )
+        );
+        assert_eq!(
+            match_custom_arg("#{name && hello}", false).unwrap(),
+            (
+                "",
+                Unit::Conditional(Conditional {
+                    arg: Box::new(Unit::Option(ClapOption {
+                        name: "name".to_string(),
+                        long: Some("name".to_string()),
+                        short: None,
+                        allow_empty_values: false,
+                        required: false,
+                        multiple: None,
+                        value_type: None,
+                        env: None,
+                    })),
+                    body: vec![Unit::Text("hello".to_string())]
                 })
             )
         );
+        assert_eq!(
+            match_custom_arg("#{docker? && docker build", false).map_err(|err| err.to_string()),
+            Err("expected literal }".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_argv() {
+        assert_eq!(
+            parse_argv(&["echo".to_string(), "#{1:name} #{@project}".to_string()]).unwrap(),
+            vec![
+                vec![Unit::Text("echo".to_string())],
+                vec![
+                    Unit::Positional(ClapPositional {
+                        name: "name".to_string(),
+                        allow_empty_values: false,
+                        required: false,
+                        index: 1,
+                        multiple: None,
+                        value_type: None,
+                        env: None,
+                    }),
+                    Unit::Text(" ".to_string()),
+                    Unit::Builtin(Builtin::new("project")),
+                ],
+            ]
+        );
+        assert!(parse_argv(&[]).is_err());
     }
 
     #[test]
@@ -700,4 +2328,65 @@ mod tests {
     fn test_match_literal_with_empty_string() {
         assert!(match_literal(":")("").is_err())
     }
+
+    #[test]
+    fn test_to_source() {
+        let sources = [
+            "cargo new $QK_PROJECT_NAME #{lib?}",
+            "echo \\\\ \\#{not an arg} \\{{not an expression}} }",
+            "#{1:name!~^[a-z]{2,\\}$} #{2:files*+\",\"} #{,a@A:int}",
+            "#{[no-]git?} #{verbose?=-v \\\\ \\}} #{@date:%Y}",
+            "#{docker? && docker build \\} #{tag}{{#if tag}}:{{tag | lower}}{{/if}}}",
+            "{{#each crates}}{{this | quote}}{{/each}}{{#if lib}}a{{else}}b{{/if}}",
+        ];
+        for source in sources {
+            let units = parse(source).unwrap();
+            assert_eq!(to_source(&units), source);
+        }
+        assert_eq!(
+            to_source(&parse("#{feature...} #{name&&a}").unwrap()),
+            "#{feature+} #{name && a}"
+        );
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut units = parse("#{name,n} #{name? && {{name | upper}}} #{,n}").unwrap();
+        units.iter_mut().for_each(|unit| {
+            unit.rename("name", "title");
+            unit.rename("n", "other");
+        });
+        assert_eq!(
+            to_source(&units),
+            "#{title,n} #{title? && {{title | upper}}} #{,n}"
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn test_parse_to_source(
+            pieces in proptest::collection::vec(
+                proptest::sample::select(vec![
+                    "a", " ", "}", "{", "#", "-", "\\\\", "\\#{", "\\{{", "\\}",
+                    "#{x}", "#{1:y!}", "#{z,z?}", "#{,w*}", "#{v+\",\"}", "#{2:u...}",
+                    "#{p:int}", "#{r~[a-z]\\}}", "#{t?=-t\\}}", "#{[no-]g?}", "#{e@E}",
+                    "#{x? && ", "#{@project}", "#{@date:%Y}", "{{x | upper}}", "{{#if x}}",
+                    "{{else}}", "{{/if}}", "{{#each x}}", "{{this}}", "{{/each}}",
+                ]),
+                0..12,
+            ),
+        ) {
+            let source = pieces.concat();
+            if let Ok(units) = parse(&source) {
+                proptest::prop_assert_eq!(parse(&to_source(&units)).unwrap(), units);
+            }
+        }
+
+        #[test]
+        fn test_parse_to_source_any_string(source in "\\PC*") {
+            if let Ok(units) = parse(&source) {
+                proptest::prop_assert_eq!(parse(&to_source(&units)).unwrap(), units);
+            }
+        }
+    }
 }
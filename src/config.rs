@@ -1,27 +1,57 @@
-use crate::{commands_parser, Command};
+use crate::commands_parser::NEGATABLE_PREFIX;
+use crate::{commands_parser, utils, Command, CommandLine, Unit};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
-use anyhow::{ensure, Result};
+use anyhow::{bail, ensure, Context, Result};
 use clap::crate_name;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+/// Names of the repository-local config files, looked for in the current directory and its parents
+pub const LOCAL_CONFIG_NAMES: &[&str] = &[".qk.toml", ".qk.yaml", ".qk.yml", ".qk.json"];
+
+/// Names that can't be used for templates because they are commands, like `qk config`
+pub const RESERVED_NAMES: &[&str] = &["config"];
+
 /// Configuration options
 ///
 /// This determines the layout of the configuration file
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     /// Default editor to execute when creating or opening projects
+    #[serde(skip_serializing_if = "Option::is_none")]
     editor: Option<String>,
 
     /// Default shell to use for executing commands when creating projects
+    #[serde(skip_serializing_if = "Option::is_none")]
     shell: Option<String>,
 
+    /// Values used as `${vars.name}` in the editor, shell and templates
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    vars: BTreeMap<String, String>,
+
+    /// Template used when only the name of the project is given, its name or one of its aliases
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default_template: Option<String>,
+
+    /// Directories where repository-local configs are used, in them or in their subdirectories.
+    /// Only read from the global config
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    trusted_dirs: Vec<PathBuf>,
+
     /// Templates to use for creating new projects
     #[serde(default)]
-    templates: HashMap<String, TemplateConfig>,
+    templates: BTreeMap<String, TemplateConfig>,
+
+    /// Files that the settings merged with [`Config::merge_local`] come from, e.g. `editor` or
+    /// `templates.rs`
+    #[serde(skip)]
+    origins: HashMap<String, PathBuf>,
 }
 
 impl Config {
@@ -33,54 +63,302 @@ impl Config {
         self.shell.as_ref()
     }
 
-    pub fn find_template(&self, template: &str) -> Option<Template> {
-        self.templates.get(template).map(Into::into)
+    /// Returns the template named `template` or with `template` as one of its aliases
+    ///
+    /// Fails if an alias is also the name of a template or is used more than once.
+    pub fn find_template(&self, template: &str) -> Result<Option<Template>> {
+        let aliases = self.aliases()?;
+        let name = aliases.get(template).copied().unwrap_or(template);
+        Ok(self
+            .templates
+            .get(name)
+            .map(|config| Template::from_config(name, config)))
+    }
+
+    /// Returns the template in `default_template`, if it's set
+    pub fn default_template(&self) -> Result<Option<Template>> {
+        let Some(name) = &self.default_template else {
+            return Ok(None);
+        };
+        let template = self
+            .find_template(name)?
+            .with_context(|| format!("default_template `{name}` is not a template"))?;
+        Ok(Some(template))
+    }
+
+    /// Returns the names of the templates by their aliases
+    fn aliases(&self) -> Result<HashMap<&str, &str>> {
+        let mut aliases = HashMap::new();
+        for (name, template) in &self.templates {
+            let TemplateConfig::Complete(template) = template else {
+                continue;
+            };
+            for alias in &template.aliases {
+                ensure!(
+                    !self.templates.contains_key(alias),
+                    "alias `{alias}` of template `{name}` is already the name of a template"
+                );
+                if let Some(other) = aliases.insert(alias.as_str(), name.as_str()) {
+                    ensure!(
+                        other != name,
+                        "alias `{alias}` is repeated in template `{name}`"
+                    );
+                    bail!("alias `{alias}` is ambiguous, both `{other}` and `{name}` use it");
+                }
+            }
+        }
+        Ok(aliases)
     }
 
     /// Returns the templates in the config
     pub fn templates(&self) -> HashMap<String, Template> {
         self.templates
             .iter()
-            .map(|(k, v)| (k.into(), v.into()))
+            .map(|(k, v)| (k.into(), Template::from_config(k, v)))
             .collect()
     }
 
-    /// Loads the config from the system's config directory
-    pub fn load() -> Result<Self> {
+    /// Returns the closest repository-local config file in `dir` or its parents, if any
+    pub fn find_local(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .flat_map(|dir| LOCAL_CONFIG_NAMES.iter().map(move |name| dir.join(name)))
+            .find(|path| path.is_file())
+    }
+
+    /// Returns whether the local config file at `path` is in one of the trusted_dirs
+    pub fn trusts(&self, path: &Path) -> bool {
+        self.trusted_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    /// Merges the config file at `path` over this config
+    ///
+    /// Its editor, shell and default_template replace the ones in this config, and so do its vars
+    /// and templates the ones with the same name. Returns the names of the templates it replaced.
+    pub fn merge_local(&mut self, path: &Path) -> Result<Vec<String>> {
+        // Its templates can use the vars of this config too
+        let local = Self::read(path)
+            .and_then(|mut local| {
+                local.vars = self.vars.clone().into_iter().chain(local.vars).collect();
+                local.resolve(path)?;
+                Ok(local)
+            })
+            .with_context(|| format!("failed loading local config {}", path.display()))?;
+        self.vars = local.vars;
+        let mut set_origin = |key: String| self.origins.insert(key, path.to_path_buf());
+        if local.editor.is_some() {
+            set_origin(String::from("editor"));
+            self.editor = local.editor;
+        }
+        if local.shell.is_some() {
+            set_origin(String::from("shell"));
+            self.shell = local.shell;
+        }
+        if local.default_template.is_some() {
+            set_origin(String::from("default_template"));
+            self.default_template = local.default_template;
+        }
+        let mut replaced = Vec::new();
+        for (name, template) in local.templates {
+            set_origin(format!("templates.{name}"));
+            if self.templates.insert(name.clone(), template).is_some() {
+                replaced.push(name);
+            }
+        }
+        // The aliases of its templates can clash with the ones of this config
+        self.aliases()
+            .with_context(|| format!("failed loading local config {}", path.display()))?;
+        Ok(replaced)
+    }
+
+    /// Returns the file that `key` comes from if it was merged with [`Config::merge_local`]
+    pub fn origin(&self, key: &str) -> Option<&Path> {
+        self.origins.get(key).map(PathBuf::as_path)
+    }
+
+    /// Returns the JSON Schema of the config, for editors to validate and autocomplete it
+    pub fn json_schema() -> String {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_string_pretty(&schema).expect("schemas can always be serialized")
+    }
+
+    /// Returns the path of the config file in the system's config directory
+    ///
+    /// It's `qk.toml`, unless it doesn't exist and there is a `qk.yaml`, `qk.yml` or `qk.json`.
+    pub fn path() -> Result<PathBuf> {
         let name = crate_name!();
-        Ok(confy::load(name, name)?)
+        let path = confy::get_configuration_file_path(name, name)?;
+        if path.exists() {
+            return Ok(path);
+        }
+        let other_path = ["yaml", "yml", "json"]
+            .iter()
+            .map(|extension| path.with_extension(extension))
+            .find(|path| path.is_file());
+        Ok(other_path.unwrap_or(path))
     }
 
-    /// Loads the config from the `path` file
-    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
-        ensure!(path.as_ref().is_file(), "config path is not a file");
-        Ok(confy::load_path(path)?)
+    /// Loads the config from the system's config directory, which is empty if there is no file
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if path.exists() {
+            return Self::load_from(path);
+        }
+        Ok(Self::default())
     }
-}
 
-impl Default for Config {
-    fn default() -> Self {
-        let mut templates = HashMap::new();
-        templates.insert(
-            String::from("example"),
-            TemplateConfig::Complete(Template {
-                projects_dir: PathBuf::from("/path/to/example/"),
-                editor: Some(String::from("code")),
-                shell: Some(String::from("bash")),
-                commands: vec![
-                    String::from("echo hello"),
-                    String::from("echo $PWD"),
-                    String::from("echo $QK_PROJECT_NAME"),
-                ],
-                name: String::from("example"),
-            }),
-        );
+    /// Returns a config with only `templates`
+    pub fn from_templates(templates: impl IntoIterator<Item = Template>) -> Self {
+        let templates = templates
+            .into_iter()
+            .map(|template| {
+                let name = template.name.clone();
+                (name, TemplateConfig::Complete(Box::new(template)))
+            })
+            .collect();
         Self {
-            editor: Some(String::from("vi")),
-            shell: Some(String::from("sh")),
             templates,
+            ..Default::default()
         }
     }
+
+    /// Loads the config from the `path` file, in the format given by its extension
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut config = Self::read(path)?;
+        config.resolve(path)?;
+        Ok(config)
+    }
+
+    /// Parses `contents` as the config file at `path`, checking and resolving its settings like
+    /// [`Config::load_from`] does
+    pub fn parse_from(path: &Path, contents: &str) -> Result<Self> {
+        let mut config = ConfigFormat::from_path(path).parse(contents)?;
+        config.resolve(path)?;
+        Ok(config)
+    }
+
+    /// Reads the config file at `path` without resolving its settings
+    fn read(path: &Path) -> Result<Self> {
+        ensure!(path.is_file(), "config path is not a file");
+        let contents = fs::read_to_string(path).context("failed reading the config file")?;
+        ConfigFormat::from_path(path).parse(&contents)
+    }
+
+    /// Replaces the vars and expands the paths in the settings of the config file at `path`
+    fn resolve(&mut self, path: &Path) -> Result<()> {
+        self.check_names()?;
+        self.aliases()?;
+        self.replace_vars()?;
+        self.expand_paths(path)
+    }
+
+    fn check_names(&self) -> Result<()> {
+        for name in RESERVED_NAMES {
+            ensure!(
+                !self.templates.contains_key(*name),
+                "a template can't be named `{name}`, it's the `qk {name}` command"
+            );
+        }
+        Ok(())
+    }
+
+    /// Replaces `${vars.name}` in the editor, shell and templates with the values in `vars`
+    fn replace_vars(&mut self) -> Result<()> {
+        let vars = &self.vars;
+        for setting in [&mut self.editor, &mut self.shell].into_iter().flatten() {
+            substitute_vars(setting, vars)?;
+        }
+        for (name, template) in &mut self.templates {
+            match template {
+                TemplateConfig::OnlyProjectsDir(projects_dir) => {
+                    substitute_vars(projects_dir, vars)
+                }
+                TemplateConfig::Complete(template) => template.replace_vars(vars),
+            }
+            .with_context(|| format!("invalid template {name:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Expands `~` and environment variables in the `projects_dir` of the templates, making them
+    /// relative to the directory of the config file at `path`
+    fn expand_paths(&mut self, path: &Path) -> Result<()> {
+        let config_dir = path.parent().unwrap_or(Path::new(""));
+        for dir in &mut self.trusted_dirs {
+            *dir = config_dir.join(
+                utils::expand_path(&dir.to_string_lossy())
+                    .with_context(|| format!("invalid trusted_dirs entry {dir:?}"))?,
+            );
+        }
+        for (name, template) in &mut self.templates {
+            let projects_dir = match template {
+                TemplateConfig::OnlyProjectsDir(projects_dir) => projects_dir.clone(),
+                TemplateConfig::Complete(template) => {
+                    template.projects_dir.to_string_lossy().to_string()
+                }
+            };
+            let projects_dir = config_dir.join(
+                utils::expand_path(&projects_dir)
+                    .with_context(|| format!("invalid projects_dir in template {name:?}"))?,
+            );
+            match template {
+                TemplateConfig::OnlyProjectsDir(dir) => {
+                    *dir = projects_dir.to_string_lossy().to_string()
+                }
+                TemplateConfig::Complete(template) => template.projects_dir = projects_dir,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Format of a config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Returns the format of the file at `path` from its extension, TOML if it isn't known
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    fn parse(self, contents: &str) -> Result<Config> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(contents)?,
+            // An empty YAML file is null instead of an empty map
+            ConfigFormat::Yaml if contents.trim().is_empty() => serde_norway::from_str("{}")?,
+            ConfigFormat::Yaml => serde_norway::from_str(contents).map_err(|err| {
+                // Unquoted values like `2024` or `true` aren't strings in YAML, which only shows
+                // as a mismatch with all the ways a setting can be written
+                let untagged = err.to_string().contains("untagged enum");
+                let err = anyhow::Error::new(err);
+                match untagged {
+                    true => err.context(
+                        "invalid YAML config, values like 2024, true or ~ need quotes to be text",
+                    ),
+                    false => err,
+                }
+            })?,
+            ConfigFormat::Json => serde_json::from_str(contents)?,
+        })
+    }
+
+    /// Returns the contents of a config file with `config` in this format
+    pub fn serialize(self, config: &Config) -> Result<String> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::to_string_pretty(config)?,
+            ConfigFormat::Yaml => serde_norway::to_string(config)?,
+            ConfigFormat::Json => serde_json::to_string_pretty(config)? + "\n",
+        })
+    }
 }
 
 /// The container of a template
@@ -94,38 +372,434 @@ impl Default for Config {
 /// [templates.example2]
 /// projects_dir = "/path/to/example2/"
 /// ```
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum TemplateConfig {
     /// Contains projects_dir
     OnlyProjectsDir(String),
-    Complete(Template),
+    Complete(Box<Template>),
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Default)]
 pub struct Template {
     #[serde(skip)]
     name: String,
 
+    /// What the template is for, shown in `qk -T --long`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+
+    /// Tags to find the template with `qk -T --tag`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+
+    /// Other names that the template can be used with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+
     /// The directory where new projects with this template will be created
+    ///
+    /// `~`, `$VAR` and `${VAR}` are expanded and relative paths are relative to the config file.
     projects_dir: PathBuf,
 
     /// The editor to execute when creating or opening projects with this template
+    #[serde(skip_serializing_if = "Option::is_none")]
     editor: Option<String>,
 
     /// The shell to use for executing commands when creating projects with this template
+    #[serde(skip_serializing_if = "Option::is_none")]
     shell: Option<String>,
 
+    /// Environment variables set for the commands of this template
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+
     #[serde(default)]
     /// The commands to execute when creating a project with this template
-    commands: Vec<String>,
+    commands: Vec<CommandConfig>,
+
+    /// Custom arguments declared once, which are used in commands with just `#{name}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    args: BTreeMap<String, ArgConfig>,
+
+    /// Groups of custom arguments, by default only one of the arguments in a group can be used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<GroupConfig>,
+
+    /// Custom arguments that can only be used with other arguments or groups
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    requires: HashMap<String, Vec<String>>,
+
+    /// Custom arguments that can't be used with other arguments or groups
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    conflicts: HashMap<String, Vec<String>>,
+}
+
+/// A custom argument declared in the `args` table of a template
+///
+/// ```toml
+/// [templates.rs.args]
+/// name = { kind = 'positional', index = 1, required = true, help = 'Name of the crate' }
+/// edition = { short = 'e', default = '2021', choices = ['2018', '2021'] }
+/// lib = { kind = 'flag' }
+/// ```
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ArgConfig {
+    #[serde(default)]
+    kind: ArgKind,
+
+    /// Position of a positional argument, starting at 1
+    index: Option<usize>,
+
+    short: Option<char>,
+
+    /// Long version of an option or flag, the name of the argument by default
+    long: Option<String>,
+
+    #[serde(default)]
+    required: bool,
+
+    /// Value used when the argument isn't passed
+    default: Option<String>,
+
+    /// Description shown in `--help`
+    help: Option<String>,
+
+    /// The only values allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    choices: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ArgKind {
+    Positional,
+    #[default]
+    Option,
+    Flag,
+}
+
+impl ArgConfig {
+    /// Returns the custom argument named `name` that references to it are replaced with
+    pub fn to_unit(&self, name: &str) -> Result<Unit> {
+        let long = self.long.as_deref().unwrap_or(name);
+        Ok(match self.kind {
+            ArgKind::Positional => {
+                ensure!(
+                    self.short.is_none() && self.long.is_none(),
+                    "positional argument `{name}` can't have `short` or `long`"
+                );
+                let index = self.index.filter(|index| *index > 0).with_context(|| {
+                    format!("positional argument `{name}` needs an `index` of at least 1")
+                })?;
+                Unit::positional(name, index, self.required)
+            }
+            ArgKind::Option => {
+                ensure!(
+                    self.index.is_none(),
+                    "`index` is only allowed in positional arguments"
+                );
+                Unit::option(name, Some(long), self.short, self.required)
+            }
+            ArgKind::Flag => {
+                ensure!(
+                    self.index.is_none()
+                        && !self.required
+                        && self.default.is_none()
+                        && self.choices.is_empty(),
+                    "flag `{name}` can't have `index`, `required`, `default` or `choices`"
+                );
+                Unit::named_flag(name, Some(long), self.short)
+            }
+        })
+    }
+
+    /// Adds the settings that can't be written with `#{}` to `arg`
+    pub fn configure(&self, mut arg: clap::Arg) -> clap::Arg {
+        if let Some(help) = &self.help {
+            arg = arg.help(help);
+        }
+        if let Some(default) = &self.default {
+            arg = arg.default_value(default);
+        }
+        if !self.choices.is_empty() {
+            arg = arg.value_parser(clap::builder::PossibleValuesParser::new(&self.choices));
+        }
+        arg
+    }
+}
+
+/// A group of custom arguments
+///
+/// ```toml
+/// groups = [{ name = 'database', args = ['sqlite', 'postgres'], required = true }]
+/// ```
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct GroupConfig {
+    name: String,
+
+    /// The custom arguments in the group
+    args: Vec<String>,
+
+    /// One of the arguments must be used
+    #[serde(default)]
+    required: bool,
+
+    /// More than one of the arguments can be used at the same time
+    #[serde(default)]
+    multiple: bool,
+}
+
+impl GroupConfig {
+    pub fn to_clap_group(&self) -> clap::ArgGroup {
+        clap::ArgGroup::new(&self.name)
+            .args(&self.args)
+            .required(self.required)
+            .multiple(self.multiple)
+    }
+}
+
+/// A command of a template
+///
+/// ```toml
+/// commands = [
+///     # Executed with the template's shell
+///     'cargo new $QK_PROJECT_NAME #{lib?}',
+///     # Executed directly, each element is exactly one argument
+///     ['cargo', 'new', '#{@project}', '#{lib?}'],
+///     # With options
+///     { run = 'npm install', cwd = '#{@project_dir}', timeout = '5m', retries = 2 },
+///     # Executed at the same time
+///     { parallel = ['npm install', { run = 'go mod download', name = 'go' }] },
+/// ]
+/// ```
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum CommandConfig {
+    Shell(String),
+    Argv(Vec<String>),
+    Complete(CompleteCommandConfig),
+    Parallel(ParallelCommandsConfig),
+}
+
+/// A group of commands that are executed at the same time
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ParallelCommandsConfig {
+    parallel: Vec<CommandConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct CompleteCommandConfig {
+    /// The command to execute, either a string executed by the shell or a list of arguments
+    run: RunConfig,
+
+    /// Time after which the command is killed and considered failed, e.g. `30s`, `5m` or `1h30m`
+    timeout: Option<String>,
+
+    /// How many times to retry the command if it fails
+    #[serde(default)]
+    retries: u32,
+
+    /// Continue creating the project even if the command fails
+    #[serde(default)]
+    allow_failure: bool,
+
+    /// Give the command the terminal instead of copying its output to the log, for programs
+    /// that ask questions or draw on the terminal
+    #[serde(default)]
+    interactive: bool,
+
+    /// The directory where the command is executed, relative to projects_dir, `~` and
+    /// environment variables are expanded
+    cwd: Option<String>,
+
+    /// Name used as prefix of the output when executed in parallel with other commands
+    name: Option<String>,
+
+    /// Only execute the command if this custom argument is used, adding a flag if there isn't one
+    when: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum RunConfig {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl RunConfig {
+    fn replace_vars(&mut self, vars: &BTreeMap<String, String>) -> Result<()> {
+        match self {
+            RunConfig::Shell(cmd) => substitute_command_vars(cmd, vars),
+            RunConfig::Argv(args) => args
+                .iter_mut()
+                .try_for_each(|arg| substitute_command_vars(arg, vars)),
+        }
+    }
+
+    fn parse(&self) -> Result<CommandLine> {
+        Ok(match self {
+            RunConfig::Shell(cmd) => CommandLine::Shell(commands_parser::parse(cmd)?),
+            RunConfig::Argv(args) => CommandLine::Argv(commands_parser::parse_argv(args)?),
+        })
+    }
+}
+
+/// A parsed command of a template with its options
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TemplateCommand {
+    pub line: CommandLine,
+    pub timeout: Option<Duration>,
+    pub retries: u32,
+    pub allow_failure: bool,
+    pub interactive: bool,
+    pub cwd: Option<Command>,
+    pub name: Option<String>,
+    /// Consecutive commands with the same group are executed at the same time
+    pub group: Option<usize>,
+    /// Name of the custom argument that must be used for the command to be executed
+    pub when: Option<String>,
+}
+
+impl TemplateCommand {
+    /// Returns all the units in this command, including the ones in its options
+    pub fn units(&self) -> Vec<&crate::Unit> {
+        let mut units = self.line.units();
+        units.extend(self.cwd.iter().flatten().flat_map(Unit::flatten));
+        units
+    }
+
+    /// Returns the name of the custom argument in `when`, which can be `[no-]name`
+    pub fn when_name(&self) -> Option<&str> {
+        let when = self.when.as_deref()?;
+        Some(when.strip_prefix(NEGATABLE_PREFIX).unwrap_or(when))
+    }
+}
+
+impl From<CommandLine> for TemplateCommand {
+    fn from(line: CommandLine) -> Self {
+        TemplateCommand {
+            line,
+            timeout: None,
+            retries: 0,
+            allow_failure: false,
+            interactive: false,
+            cwd: None,
+            name: None,
+            group: None,
+            when: None,
+        }
+    }
+}
+
+impl CommandConfig {
+    fn replace_vars(&mut self, vars: &BTreeMap<String, String>) -> Result<()> {
+        match self {
+            CommandConfig::Shell(cmd) => substitute_command_vars(cmd, vars),
+            CommandConfig::Argv(args) => args
+                .iter_mut()
+                .try_for_each(|arg| substitute_command_vars(arg, vars)),
+            CommandConfig::Complete(cmd) => {
+                cmd.run.replace_vars(vars)?;
+                cmd.cwd
+                    .iter_mut()
+                    .try_for_each(|cwd| substitute_command_vars(cwd, vars))
+            }
+            CommandConfig::Parallel(group) => group
+                .parallel
+                .iter_mut()
+                .try_for_each(|cmd| cmd.replace_vars(vars)),
+        }
+    }
+
+    fn parse(&self) -> Result<TemplateCommand> {
+        Ok(match self {
+            CommandConfig::Shell(cmd) => RunConfig::Shell(cmd.clone()).parse()?.into(),
+            CommandConfig::Argv(args) => RunConfig::Argv(args.clone()).parse()?.into(),
+            CommandConfig::Complete(config) => TemplateCommand {
+                line: config.run.parse()?,
+                timeout: config
+                    .timeout
+                    .as_deref()
+                    .map(utils::parse_duration)
+                    .transpose()
+                    .context("invalid timeout")?,
+                retries: config.retries,
+                allow_failure: config.allow_failure,
+                interactive: config.interactive,
+                cwd: config
+                    .cwd
+                    .as_deref()
+                    .map(|cwd| parse_cwd(cwd, utils::home_dir().as_deref(), utils::env_var))
+                    .transpose()?,
+                name: config.name.clone(),
+                group: None,
+                when: config.when.clone(),
+            },
+            CommandConfig::Parallel(_) => bail!("parallel groups can't be nested"),
+        })
+    }
+}
+
+/// Parses `cwd`, expanding `~` to `home` and environment variables from `var` only in its text
+///
+/// Expanding before parsing would let the value of a variable be parsed as a placeholder.
+fn parse_cwd(
+    cwd: &str,
+    home: Option<&str>,
+    var: impl Fn(&str) -> Option<String> + Copy,
+) -> Result<Command> {
+    let mut units = commands_parser::parse(cwd)?;
+    for (index, unit) in units.iter_mut().enumerate() {
+        if let Unit::Text(text) = unit {
+            // `~` is only the home directory at the beginning of the path
+            *text = match index {
+                0 => utils::expand_path_with(text, home, var)?,
+                _ => utils::expand_env(text, var)?,
+            };
+        }
+    }
+    Ok(units)
 }
 
 impl Template {
+    pub fn new(name: &str, projects_dir: impl Into<PathBuf>, commands: Vec<CommandConfig>) -> Self {
+        Template {
+            name: name.to_string(),
+            projects_dir: projects_dir.into(),
+            commands,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the template named `name` in the config
+    fn from_config(name: &str, config: &TemplateConfig) -> Template {
+        Template {
+            name: name.to_string(),
+            ..config.into()
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn description(&self) -> Option<&String> {
+        self.description.as_ref()
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
     pub fn projects_dir(&self) -> &Path {
         &self.projects_dir
     }
@@ -138,15 +812,205 @@ impl Template {
         self.shell.as_ref()
     }
 
+    /// Returns the commands in this template as written in the config
+    pub fn command_configs(&self) -> &[CommandConfig] {
+        &self.commands
+    }
+
+    pub fn args(&self) -> &BTreeMap<String, ArgConfig> {
+        &self.args
+    }
+
+    pub fn groups(&self) -> &[GroupConfig] {
+        &self.groups
+    }
+
+    pub fn requires(&self) -> &HashMap<String, Vec<String>> {
+        &self.requires
+    }
+
+    pub fn conflicts(&self) -> &HashMap<String, Vec<String>> {
+        &self.conflicts
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    /// Replaces `${vars.name}` in the settings of this template with the values in `vars`
+    fn replace_vars(&mut self, vars: &BTreeMap<String, String>) -> Result<()> {
+        if let Some(projects_dir) = self.projects_dir.to_str() {
+            let mut projects_dir = projects_dir.to_string();
+            substitute_vars(&mut projects_dir, vars)?;
+            self.projects_dir = PathBuf::from(projects_dir);
+        }
+        let settings = [&mut self.editor, &mut self.shell].into_iter().flatten();
+        for setting in settings.chain(self.env.values_mut()) {
+            substitute_vars(setting, vars)?;
+        }
+        for command in &mut self.commands {
+            command.replace_vars(vars)?;
+        }
+        Ok(())
+    }
+
     /// Returns the commands in this template after parsing them
-    pub fn commands(&self) -> Result<Vec<Command>> {
+    pub fn commands(&self) -> Result<Vec<TemplateCommand>> {
         let mut commands = vec![];
-        for cmd in self.commands.iter() {
-            let units = commands_parser::parse(cmd)?;
-            commands.push(units);
+        for (index, cmd) in self.commands.iter().enumerate() {
+            if let CommandConfig::Parallel(group) = cmd {
+                for cmd in &group.parallel {
+                    let cmd = cmd.parse()?;
+                    ensure!(
+                        !cmd.interactive,
+                        "interactive commands can't be executed in parallel"
+                    );
+                    commands.push(TemplateCommand {
+                        group: Some(index),
+                        ..cmd
+                    });
+                }
+            } else {
+                commands.push(cmd.parse()?);
+            }
         }
+
+        self.replace_references(&mut commands)?;
+        self.validate_args(&commands)?;
         Ok(commands)
     }
+
+    /// Returns the custom arguments of the template as they are used in the command line, like
+    /// `<name>`, `[--lib]` or `--edition <edition>`, positional arguments first
+    pub fn usage_args(&self) -> Result<Vec<String>> {
+        let commands = self.commands()?;
+        let declared = self
+            .args
+            .iter()
+            .map(|(name, arg)| arg.to_unit(name))
+            .collect::<Result<Vec<_>>>()?;
+        let units = commands
+            .iter()
+            .flat_map(TemplateCommand::units)
+            .chain(&declared)
+            .collect();
+        // Arguments can only be displayed once clap has filled in their defaults
+        let mut cmd = clap::Command::new(self.name().to_string())
+            .disable_help_flag(true)
+            .args(Unit::to_clap_args(units));
+        cmd.build();
+        let mut args: Vec<_> = cmd.get_arguments().collect();
+        args.sort_by_key(|arg| (arg.get_index().unwrap_or(usize::MAX), arg.get_id()));
+        Ok(args
+            .into_iter()
+            // Optional positional arguments already have brackets
+            .map(|arg| match arg.is_required_set() || arg.is_positional() {
+                true => arg.to_string(),
+                false => format!("[{arg}]"),
+            })
+            .collect())
+    }
+
+    /// Replaces `#{name}` with the custom argument declared in `args`, if it's declared there
+    fn replace_references(&self, commands: &mut [TemplateCommand]) -> Result<()> {
+        let mut declared = HashMap::new();
+        for (name, arg) in &self.args {
+            let unit = arg
+                .to_unit(name)
+                .with_context(|| format!("invalid custom argument `{name}` in `args`"))?;
+            declared.insert(name.as_str(), unit);
+        }
+        let mut replace = |unit: &mut Unit| {
+            if let Some(arg) = unit.name().and_then(|name| declared.get(name)) {
+                let name = arg.name().unwrap();
+                ensure!(
+                    *unit == Unit::option(name, Some(name), None, false),
+                    "custom argument `{name}` is declared in `args`, it must be used as `#{{{name}}}`"
+                );
+                *unit = arg.clone();
+            }
+            Ok(())
+        };
+        for command in commands {
+            command.line.visit_args_mut(&mut replace)?;
+            for unit in command.cwd.iter_mut().flatten() {
+                unit.visit_args_mut(&mut replace)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that custom arguments are declared the same way everywhere and that the names used
+    /// outside of `#{}`, `when` and `args` are declared
+    fn validate_args(&self, commands: &[TemplateCommand]) -> Result<()> {
+        let mut args_by_name: HashMap<&str, &Unit> = HashMap::new();
+        for unit in commands.iter().flat_map(TemplateCommand::units) {
+            if let Some(name) = unit.name() {
+                if let Some(other) = args_by_name.insert(name, unit) {
+                    ensure!(
+                        other == unit,
+                        "custom argument `{name}` is declared differently in `{other}` and `{unit}`, \
+                         consider declaring it once in `args`"
+                    );
+                }
+            }
+        }
+        let declared: HashSet<&str> = args_by_name
+            .into_keys()
+            .chain(commands.iter().filter_map(TemplateCommand::when_name))
+            .chain(self.args.keys().map(String::as_str))
+            .collect();
+        for unit in commands.iter().flat_map(TemplateCommand::units) {
+            if let Some(name) = unit.reference() {
+                ensure!(
+                    declared.contains(name),
+                    "unknown custom argument `{name}` in expression, it must be declared with `#{{{name}}}`"
+                );
+            }
+        }
+
+        let mut groups = HashSet::new();
+        for group in &self.groups {
+            ensure!(
+                !declared.contains(group.name.as_str()),
+                "group `{}` has the same name as a custom argument",
+                group.name
+            );
+            ensure!(
+                groups.insert(group.name.as_str()),
+                "there is more than one group named `{}`",
+                group.name
+            );
+            ensure!(
+                !group.args.is_empty(),
+                "group `{}` has no custom arguments",
+                group.name
+            );
+            for arg in &group.args {
+                ensure!(
+                    declared.contains(arg.as_str()),
+                    "unknown custom argument `{arg}` in group `{}`",
+                    group.name
+                );
+            }
+        }
+
+        for (field, relations) in [("requires", &self.requires), ("conflicts", &self.conflicts)] {
+            for (arg, others) in relations {
+                ensure!(
+                    declared.contains(arg.as_str()),
+                    "unknown custom argument `{arg}` in `{field}`"
+                );
+                for other in others {
+                    ensure!(
+                        declared.contains(other.as_str()) || groups.contains(other.as_str()),
+                        "unknown custom argument or group `{other}` in `{field}.{arg}`"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl From<&TemplateConfig> for Template {
@@ -154,35 +1018,79 @@ impl From<&TemplateConfig> for Template {
         match template {
             TemplateConfig::OnlyProjectsDir(projects_dir) => Template {
                 projects_dir: projects_dir.into(),
-                editor: None,
-                shell: None,
-                commands: Vec::new(),
-                name: String::from(""),
+                ..Default::default()
             },
-            TemplateConfig::Complete(template) => template.clone(),
+            TemplateConfig::Complete(template) => template.as_ref().clone(),
+        }
+    }
+}
+
+/// Replaces every `${vars.name}` in `text` with the value of `name` in `vars`
+fn substitute_vars(text: &mut String, vars: &BTreeMap<String, String>) -> Result<()> {
+    const PREFIX: &str = "${vars.";
+    if !text.contains(PREFIX) {
+        return Ok(());
+    }
+    let mut substituted = String::new();
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find(PREFIX) {
+        substituted.push_str(&rest[..start]);
+        rest = &rest[start + PREFIX.len()..];
+        let end = rest
+            .find('}')
+            .with_context(|| format!("missing `}}` after `${{vars.` in {text:?}"))?;
+        let name = &rest[..end];
+        let value = vars
+            .get(name)
+            .with_context(|| format!("unknown variable `{name}`, it isn't in `vars`"))?;
+        substituted.push_str(value);
+        rest = &rest[end + 1..];
+    }
+    substituted.push_str(rest);
+    *text = substituted;
+    Ok(())
+}
+
+/// Like [`substitute_vars`], for text that's parsed as a command afterwards
+///
+/// The values are inserted as they are, so they can't have the syntax of commands, which would be
+/// parsed as part of the command instead of as text.
+fn substitute_command_vars(text: &mut String, vars: &BTreeMap<String, String>) -> Result<()> {
+    const COMMAND_SYNTAX: &[&str] = &["#{", "{{", "\\"];
+    for (name, value) in vars {
+        if !text.contains(&format!("${{vars.{name}}}")) {
+            continue;
+        }
+        if let Some(syntax) = COMMAND_SYNTAX.iter().find(|syntax| value.contains(*syntax)) {
+            bail!("variable `{name}` can't be used in commands, its value has `{syntax}`");
         }
     }
+    substitute_vars(text, vars)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Unit;
+    use crate::commands_parser::Builtin;
 
     #[test]
     fn test_find_template_returns_none_when_empty() {
         let config = Config {
             editor: None,
             shell: None,
-            templates: HashMap::new(),
+            vars: BTreeMap::new(),
+            default_template: None,
+            trusted_dirs: Vec::new(),
+            templates: BTreeMap::new(),
+            origins: HashMap::new(),
         };
 
-        assert_eq!(config.find_template("a"), None);
+        assert_eq!(config.find_template("a").unwrap(), None);
     }
 
     #[test]
     fn test_find_template_returns_none_when_not_present() {
-        let mut templates = HashMap::new();
+        let mut templates = BTreeMap::new();
         templates.insert(
             String::from("a"),
             TemplateConfig::OnlyProjectsDir(String::from("a")),
@@ -190,15 +1098,19 @@ mod tests {
         let config = Config {
             editor: None,
             shell: None,
+            vars: BTreeMap::new(),
+            default_template: None,
+            trusted_dirs: Vec::new(),
             templates,
+            origins: HashMap::new(),
         };
 
-        assert_eq!(config.find_template("b"), None);
+        assert_eq!(config.find_template("b").unwrap(), None);
     }
 
     #[test]
     fn test_find_template_returns_the_template() {
-        let mut templates = HashMap::new();
+        let mut templates = BTreeMap::new();
 
         templates.insert(
             String::from("a"),
@@ -218,24 +1130,29 @@ mod tests {
         let config = Config {
             editor: None,
             shell: None,
+            vars: BTreeMap::new(),
+            default_template: None,
+            trusted_dirs: Vec::new(),
             templates,
+            origins: HashMap::new(),
         };
 
         assert_eq!(
-            config.find_template("b"),
+            config.find_template("b").unwrap(),
             Some(Template {
                 projects_dir: PathBuf::from("b"),
                 editor: None,
                 shell: None,
                 commands: vec![],
-                name: String::from("")
+                name: String::from("b"),
+                ..Default::default()
             })
         );
     }
 
     #[test]
     fn test_templates() {
-        let mut templates = HashMap::new();
+        let mut templates = BTreeMap::new();
 
         templates.insert(
             String::from("a"),
@@ -244,13 +1161,14 @@ mod tests {
 
         templates.insert(
             String::from("b"),
-            TemplateConfig::Complete(Template {
+            TemplateConfig::Complete(Box::new(Template {
                 projects_dir: PathBuf::from("b"),
                 editor: Some(String::from("vi")),
                 shell: Some(String::from("zsh")),
-                commands: vec![String::from("echo hello")],
+                commands: vec![CommandConfig::Shell(String::from("echo hello"))],
                 name: String::from("b"),
-            }),
+                ..Default::default()
+            })),
         );
 
         templates.insert(
@@ -261,7 +1179,11 @@ mod tests {
         let config = Config {
             editor: None,
             shell: None,
+            vars: BTreeMap::new(),
+            default_template: None,
+            trusted_dirs: Vec::new(),
             templates,
+            origins: HashMap::new(),
         };
 
         let mut expected_templates = HashMap::new();
@@ -273,7 +1195,8 @@ mod tests {
                 editor: None,
                 shell: None,
                 commands: vec![],
-                name: String::from(""),
+                name: String::from("a"),
+                ..Default::default()
             },
         );
 
@@ -283,8 +1206,9 @@ mod tests {
                 projects_dir: PathBuf::from("b"),
                 editor: Some(String::from("vi")),
                 shell: Some(String::from("zsh")),
-                commands: vec![String::from("echo hello")],
+                commands: vec![CommandConfig::Shell(String::from("echo hello"))],
                 name: String::from("b"),
+                ..Default::default()
             },
         );
 
@@ -295,23 +1219,182 @@ mod tests {
                 editor: None,
                 shell: None,
                 commands: vec![],
-                name: String::from(""),
+                name: String::from("c"),
+                ..Default::default()
             },
         );
 
         assert_eq!(config.templates(), expected_templates);
     }
 
+    #[test]
+    fn test_find_template_by_alias() {
+        let config: Config = toml::from_str(
+            "
+            default_template = 'r'
+            [templates]
+            py = '/py'
+            rust-projects = { projects_dir = '/rs', aliases = ['r', 'rust'] }
+            ",
+        )
+        .unwrap();
+
+        let template = config.find_template("rust").unwrap().unwrap();
+        assert_eq!(template.name(), "rust-projects");
+        assert_eq!(template.aliases(), ["r", "rust"]);
+        assert_eq!(config.find_template("py").unwrap().unwrap().name(), "py");
+        assert_eq!(config.find_template("rs").unwrap(), None);
+        assert_eq!(
+            config.default_template().unwrap().unwrap().name(),
+            "rust-projects"
+        );
+    }
+
+    #[test]
+    fn test_find_template_invalid_aliases() {
+        let error = |templates: &str| {
+            let config: Config = toml::from_str(&format!("[templates]\n{templates}")).unwrap();
+            config.find_template("a").unwrap_err().to_string()
+        };
+        assert_eq!(
+            error("a = { projects_dir = '/a', aliases = ['b'] }\nb = '/b'"),
+            "alias `b` of template `a` is already the name of a template"
+        );
+        assert_eq!(
+            error("a = { projects_dir = '/a', aliases = ['x'] }\nb = { projects_dir = '/b', aliases = ['x'] }"),
+            "alias `x` is ambiguous, both `a` and `b` use it"
+        );
+        assert_eq!(
+            error("a = { projects_dir = '/a', aliases = ['x', 'x'] }"),
+            "alias `x` is repeated in template `a`"
+        );
+
+        let config: Config = toml::from_str("default_template = 'a'").unwrap();
+        assert_eq!(
+            config.default_template().unwrap_err().to_string(),
+            "default_template `a` is not a template"
+        );
+    }
+
+    #[test]
+    fn test_usage_args() {
+        let config: Config = toml::from_str(
+            "
+            [templates.rs]
+            projects_dir = '/rs'
+            commands = ['cargo new #{1:name!} #{lib?} #{2:dir} #{edition}', 'echo #{@project}']
+            args = { edition = { short = 'e', default = '2021' } }
+            ",
+        )
+        .unwrap();
+        let template = config.find_template("rs").unwrap().unwrap();
+        assert_eq!(
+            template.usage_args().unwrap(),
+            ["<name>", "[dir]", "[--edition <edition>]", "[--lib]"]
+        );
+    }
+
+    #[test]
+    fn test_substitute_vars() {
+        let vars = BTreeMap::from([
+            (String::from("code"), String::from("~/code")),
+            (String::from("org"), String::from("acme")),
+        ]);
+        let substitute = |text: &str| {
+            let mut text = text.to_string();
+            substitute_vars(&mut text, &vars).map(|()| text)
+        };
+        assert_eq!(
+            substitute("${vars.code}/${vars.org} ${HOME} #{name}").unwrap(),
+            "~/code/acme ${HOME} #{name}"
+        );
+        assert_eq!(
+            substitute("${vars.nope}").unwrap_err().to_string(),
+            "unknown variable `nope`, it isn't in `vars`"
+        );
+        assert!(substitute("${vars.code").is_err());
+    }
+
+    #[test]
+    fn test_substitute_command_vars() {
+        let vars = BTreeMap::from([
+            (String::from("org"), String::from("acme")),
+            (String::from("arg"), String::from("#{name}")),
+            (String::from("path"), String::from("C:\\code")),
+        ]);
+        let substitute = |text: &str| {
+            let mut text = text.to_string();
+            substitute_command_vars(&mut text, &vars).map(|()| text)
+        };
+        assert_eq!(
+            substitute("echo ${vars.org} #{name}").unwrap(),
+            "echo acme #{name}"
+        );
+        assert_eq!(
+            substitute("echo ${vars.arg}").unwrap_err().to_string(),
+            "variable `arg` can't be used in commands, its value has `#{`"
+        );
+        assert_eq!(
+            substitute("cd ${vars.path}").unwrap_err().to_string(),
+            "variable `path` can't be used in commands, its value has `\\`"
+        );
+    }
+
+    #[test]
+    fn test_replace_vars() {
+        let mut config: Config = toml::from_str(
+            "
+            shell = '${vars.code}/bin/sh'
+            [vars]
+            code = '/code'
+            org = 'acme'
+            [templates]
+            a = '${vars.code}/a'
+            [templates.b]
+            projects_dir = '${vars.code}/b'
+            env = { ORG = '${vars.org}' }
+            commands = [
+                'gh repo create ${vars.org}/$QK_PROJECT_NAME',
+                { run = ['echo', '${vars.org}'], cwd = '${vars.org}' },
+                { parallel = ['echo ${vars.org}'] },
+            ]
+            ",
+        )
+        .unwrap();
+        config.replace_vars().unwrap();
+
+        assert_eq!(config.shell().unwrap(), "/code/bin/sh");
+        let a = config.find_template("a").unwrap().unwrap();
+        assert_eq!(a.projects_dir(), Path::new("/code/a"));
+        let b = config.find_template("b").unwrap().unwrap();
+        assert_eq!(b.projects_dir(), Path::new("/code/b"));
+        assert_eq!(b.env()["ORG"], "acme");
+        let expected: Vec<CommandConfig> = toml::from_str::<HashMap<String, _>>(
+            "
+            commands = [
+                'gh repo create acme/$QK_PROJECT_NAME',
+                { run = ['echo', 'acme'], cwd = 'acme' },
+                { parallel = ['echo acme'] },
+            ]
+            ",
+        )
+        .unwrap()
+        .remove("commands")
+        .unwrap();
+        assert_eq!(b.command_configs(), expected);
+    }
+
     #[test]
     fn test_from_template_config() {
         let template_config1 = TemplateConfig::OnlyProjectsDir(String::from("a"));
-        let template_config2 = TemplateConfig::Complete(Template {
+        let template_config2 = TemplateConfig::Complete(Box::new(Template {
             projects_dir: PathBuf::from("b"),
             editor: Some(String::from("vi")),
             shell: Some(String::from("fish")),
-            commands: vec![String::from("echo hello")],
+            commands: vec![CommandConfig::Shell(String::from("echo hello"))],
             name: String::from("b"),
-        });
+            ..Default::default()
+        }));
 
         let template1: Template = (&template_config1).into();
         let template2: Template = (&template_config2).into();
@@ -323,7 +1406,8 @@ mod tests {
                 editor: None,
                 shell: None,
                 commands: vec![],
-                name: String::from("")
+                name: String::from(""),
+                ..Default::default()
             }
         );
 
@@ -333,8 +1417,9 @@ mod tests {
                 projects_dir: PathBuf::from("b"),
                 editor: Some(String::from("vi")),
                 shell: Some(String::from("fish")),
-                commands: vec![String::from("echo hello")],
-                name: String::from("b")
+                commands: vec![CommandConfig::Shell(String::from("echo hello"))],
+                name: String::from("b"),
+                ..Default::default()
             }
         );
     }
@@ -346,14 +1431,413 @@ mod tests {
             projects_dir: "a".into(),
             editor: None,
             shell: None,
-            commands: vec![String::from("echo hello world"), String::from("echo hey!")],
+            commands: vec![
+                CommandConfig::Shell(String::from("echo hello world")),
+                CommandConfig::Shell(String::from("echo hey!")),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            template.commands().unwrap(),
+            vec![
+                CommandLine::Shell(vec![Unit::Text("echo hello world".to_string())]).into(),
+                CommandLine::Shell(vec![Unit::Text("echo hey!".to_string())]).into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_argv_commands() {
+        let template = Template {
+            name: "a".to_string(),
+            projects_dir: "a".into(),
+            editor: None,
+            shell: None,
+            commands: vec![CommandConfig::Argv(vec![
+                String::from("cargo"),
+                String::from("new"),
+                String::from("#{@project}"),
+            ])],
+            ..Default::default()
         };
 
         assert_eq!(
             template.commands().unwrap(),
+            vec![CommandLine::Argv(vec![
+                vec![Unit::Text("cargo".to_string())],
+                vec![Unit::Text("new".to_string())],
+                vec![Unit::Builtin(Builtin::new("project"))],
+            ])
+            .into()]
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_complete_commands() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [
+                { run = 'npm install', timeout = '1m30s', retries = 2, cwd = '#{@project_dir}' },
+                { run = ['false'], allow_failure = true, when = 'ci' },
+            ]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+
+        assert_eq!(
+            template.commands().unwrap(),
+            vec![
+                TemplateCommand {
+                    line: CommandLine::Shell(vec![Unit::Text("npm install".to_string())]),
+                    timeout: Some(Duration::from_secs(90)),
+                    retries: 2,
+                    allow_failure: false,
+                    interactive: false,
+                    cwd: Some(vec![Unit::Builtin(Builtin::new("project_dir"))]),
+                    name: None,
+                    group: None,
+                    when: None,
+                },
+                TemplateCommand {
+                    line: CommandLine::Argv(vec![vec![Unit::Text("false".to_string())]]),
+                    timeout: None,
+                    retries: 0,
+                    allow_failure: true,
+                    interactive: false,
+                    cwd: None,
+                    name: None,
+                    group: None,
+                    when: Some("ci".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_parallel_interactive_command() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [{ parallel = ['echo one', { run = 'npm init', interactive = true }] }]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+        assert_eq!(
+            template.commands().map_err(|err| err.to_string()),
+            Err("interactive commands can't be executed in parallel".to_string())
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_parallel_commands() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [
+                'echo one',
+                { parallel = ['echo two', { run = ['echo', 'three'], name = 'three' }] },
+            ]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+
+        assert_eq!(
+            template.commands().unwrap(),
+            vec![
+                CommandLine::Shell(vec![Unit::Text("echo one".to_string())]).into(),
+                TemplateCommand {
+                    group: Some(1),
+                    ..CommandLine::Shell(vec![Unit::Text("echo two".to_string())]).into()
+                },
+                TemplateCommand {
+                    name: Some("three".to_string()),
+                    group: Some(1),
+                    ..CommandLine::Argv(vec![
+                        vec![Unit::Text("echo".to_string())],
+                        vec![Unit::Text("three".to_string())]
+                    ])
+                    .into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_nested_parallel_commands() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [{ parallel = [{ parallel = ['echo one'] }] }]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+
+        assert!(template.commands().is_err());
+    }
+
+    #[test]
+    fn test_commands_method_with_expressions() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [
+                'echo #{1:name}',
+                { run = 'echo {{name | upper}} {{#if ci}}ci{{/if}}', when = '[no-]ci' },
+            ]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+        assert!(template.commands().is_ok());
+
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = ['echo {{name}}']
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+        assert_eq!(
+            template.commands().map_err(|err| err.to_string()),
+            Err(
+                "unknown custom argument `name` in expression, it must be declared with `#{name}`"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_commands_method_with_arg_relations() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = ['echo #{sqlite?} #{postgres?} #{db-url}']
+            groups = [{ name = 'database', args = ['sqlite', 'postgres'], required = true }]
+            requires = { db-url = ['postgres'] }
+            conflicts = { db-url = ['sqlite'] }
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+        assert!(template.commands().is_ok());
+
+        let errors = [
+            (
+                "groups = [{ name = 'database', args = ['sqlite', 'mysql'] }]",
+                "unknown custom argument `mysql` in group `database`",
+            ),
+            (
+                "groups = [{ name = 'sqlite', args = ['postgres'] }]",
+                "group `sqlite` has the same name as a custom argument",
+            ),
+            (
+                "groups = [{ name = 'a', args = ['sqlite'] }, { name = 'a', args = ['postgres'] }]",
+                "there is more than one group named `a`",
+            ),
+            (
+                "groups = [{ name = 'database', args = [] }]",
+                "group `database` has no custom arguments",
+            ),
+            (
+                "requires = { mysql = ['postgres'] }",
+                "unknown custom argument `mysql` in `requires`",
+            ),
+            (
+                "conflicts = { sqlite = ['database'] }",
+                "unknown custom argument or group `database` in `conflicts.sqlite`",
+            ),
+        ];
+        for (relations, error) in errors {
+            let config: TemplateConfig = toml::from_str(&format!(
+                "
+                projects_dir = 'a'
+                commands = ['echo #{{sqlite?}} #{{postgres?}}']
+                {relations}
+                "
+            ))
+            .unwrap();
+            let template: Template = (&config).into();
+            assert_eq!(
+                template.commands().map_err(|err| err.to_string()),
+                Err(error.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_commands_method_with_declared_args() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = ['new #{name}', 'echo #{lib && lib} #{edition}']
+            [args]
+            name = { kind = 'positional', index = 1, required = true }
+            edition = { short = 'e', long = 'ed', default = '2021' }
+            lib = { kind = 'flag' }
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+        let commands = template.commands().unwrap();
+        assert_eq!(
+            commands[0].line,
+            CommandLine::Shell(vec![
+                Unit::Text(String::from("new ")),
+                Unit::positional("name", 1, true),
+            ])
+        );
+        let units = commands[1].line.units();
+        assert!(units.contains(&&Unit::named_flag("lib", Some("lib"), None)));
+        assert!(units.contains(&&Unit::option("edition", Some("ed"), Some('e'), false)));
+
+        let errors = [
+            (
+                "commands = ['echo #{name!}']\n[args]\nname = {}",
+                "custom argument `name` is declared in `args`, it must be used as `#{name}`",
+            ),
+            (
+                "commands = ['echo #{1:name}']\n[args]\nname = { kind = 'positional' }",
+                "invalid custom argument `name` in `args`",
+            ),
+            (
+                "commands = ['echo #{name}', 'echo #{name!}']",
+                "custom argument `name` is declared differently in `#{name}` and `#{name!}`, \
+                 consider declaring it once in `args`",
+            ),
+        ];
+        for (commands, error) in errors {
+            let config: TemplateConfig =
+                toml::from_str(&format!("projects_dir = 'a'\n{commands}")).unwrap();
+            let template: Template = (&config).into();
+            assert_eq!(
+                template.commands().map_err(|err| err.to_string()),
+                Err(error.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_formats() {
+        let toml = "
+            editor = 'vi'
+            [templates]
+            a = '/a'
+            [templates.b]
+            projects_dir = '/b'
+            commands = ['echo hello', ['ls', '-a'], { run = 'make', timeout = '1m' }]
+        ";
+        let yaml = "
+            editor: vi
+            templates:
+              a: /a
+              b:
+                projects_dir: /b
+                commands:
+                  - echo hello
+                  - [ls, -a]
+                  - { run: make, timeout: 1m }
+        ";
+        let json = r#"{
+            "editor": "vi",
+            "templates": {
+                "a": "/a",
+                "b": {
+                    "projects_dir": "/b",
+                    "commands": ["echo hello", ["ls", "-a"], { "run": "make", "timeout": "1m" }]
+                }
+            }
+        }"#;
+        let toml = ConfigFormat::Toml.parse(toml).unwrap();
+        for config in [
+            ConfigFormat::Yaml.parse(yaml).unwrap(),
+            ConfigFormat::Json.parse(json).unwrap(),
+        ] {
+            assert_eq!(config.editor(), toml.editor());
+            assert_eq!(config.templates(), toml.templates());
+        }
+        assert!(ConfigFormat::Yaml.parse("").is_ok());
+
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("a/qk.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("qk.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(ConfigFormat::from_path(Path::new("qk")), ConfigFormat::Toml);
+    }
+
+    #[test]
+    fn test_parse_yaml_implicit_types() {
+        for yaml in [
+            "templates: { rs: 2024 }",
+            "templates: { rs: true }",
+            "templates: { rs: { projects_dir: /rs, commands: [2024] } }",
+        ] {
+            let err = ConfigFormat::Yaml.parse(yaml).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "invalid YAML config, values like 2024, true or ~ need quotes to be text",
+                "{yaml}"
+            );
+        }
+        let config = ConfigFormat::Yaml
+            .parse("templates: { rs: '2024' }")
+            .unwrap();
+        assert_eq!(
+            config.find_template("rs").unwrap().unwrap().projects_dir(),
+            Path::new("2024")
+        );
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema: serde_json::Value = serde_json::from_str(&Config::json_schema()).unwrap();
+        assert_eq!(schema["title"], "Config");
+        let template_config = &schema["$defs"]["TemplateConfig"]["anyOf"];
+        assert_eq!(template_config[0]["type"], "string");
+        assert_eq!(template_config[1]["$ref"], "#/$defs/Template");
+        let template = &schema["$defs"]["Template"]["properties"];
+        assert_eq!(template["projects_dir"]["type"], "string");
+        assert!(template.get("name").is_none());
+    }
+
+    #[test]
+    fn test_commands_method_with_invalid_timeout() {
+        let config: TemplateConfig = toml::from_str(
+            "
+            projects_dir = 'a'
+            commands = [{ run = 'npm install', timeout = '5 minutes' }]
+            ",
+        )
+        .unwrap();
+        let template: Template = (&config).into();
+
+        assert!(template.commands().is_err());
+    }
+
+    #[test]
+    fn test_parse_cwd() {
+        let var = |name: &str| (name == "QK_TEST_PARSE_CWD").then(|| "#{name}".to_string());
+        assert_eq!(
+            parse_cwd(
+                "~/a/$QK_TEST_PARSE_CWD/#{@project_dir}/~",
+                Some("/home/me"),
+                var
+            )
+            .unwrap(),
             vec![
-                vec![Unit::Text("echo hello world".to_string())],
-                vec![Unit::Text("echo hey!".to_string())]
+                Unit::Text("/home/me/a/#{name}/".to_string()),
+                Unit::Builtin(Builtin::new("project_dir")),
+                Unit::Text("/~".to_string()),
             ]
         );
     }
@@ -0,0 +1,205 @@
+use crate::config::ConfigFormat;
+use crate::Config;
+
+use anyhow::{bail, ensure, Context, Result};
+use toml_edit::{value, Array, DocumentMut, InlineTable, Item, Table, Value};
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Keys that can be changed with `qk config set`
+pub const SETTABLE_KEYS: &[&str] = &["editor", "shell", "projects_dir"];
+
+/// The config file as written by the user, to change it keeping its comments and formatting
+#[derive(Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    document: DocumentMut,
+}
+
+impl ConfigFile {
+    /// Reads the config file at `path`, which is empty if it doesn't exist yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        ensure!(
+            ConfigFormat::from_path(&path) == ConfigFormat::Toml,
+            "only TOML config files can be changed, use `qk config edit` instead"
+        );
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err).context("failed reading the config file"),
+        };
+        let document = contents.parse().context("failed parsing the config file")?;
+        Ok(Self { path, document })
+    }
+
+    /// Adds a template, failing if there is already one with the same name
+    pub fn add_template(
+        &mut self,
+        name: &str,
+        projects_dir: &str,
+        commands: &[String],
+    ) -> Result<()> {
+        let templates = self.templates()?;
+        ensure!(
+            !templates.contains_key(name),
+            "template {name:?} already exists"
+        );
+        let mut template = Table::new();
+        template.insert("projects_dir", value(projects_dir));
+        if !commands.is_empty() {
+            let mut array: Array = commands.iter().map(String::as_str).collect();
+            if commands.len() > 1 {
+                array.iter_mut().for_each(|command| {
+                    command.decor_mut().set_prefix("\n    ");
+                });
+                array.set_trailing("\n");
+                array.set_trailing_comma(true);
+            }
+            template.insert("commands", value(array));
+        }
+        templates.insert(name, Item::Table(template));
+        Ok(())
+    }
+
+    pub fn remove_template(&mut self, name: &str) -> Result<()> {
+        self.templates()?
+            .remove(name)
+            .with_context(|| format!("template {name:?} not found"))?;
+        Ok(())
+    }
+
+    /// Sets `key` in `template`, or at the beginning of the config if there is no template
+    pub fn set(&mut self, key: &str, new_value: &str, template: Option<&str>) -> Result<()> {
+        ensure!(
+            SETTABLE_KEYS.contains(&key),
+            "unknown key `{key}`, expected one of: {}",
+            SETTABLE_KEYS.join(", ")
+        );
+        let Some(name) = template else {
+            ensure!(
+                key != "projects_dir",
+                "`projects_dir` can only be set in a template"
+            );
+            set_value(&mut self.document[key], new_value);
+            return Ok(());
+        };
+
+        let templates = self.templates()?;
+        let template = templates
+            .get_mut(name)
+            .with_context(|| format!("template {name:?} not found"))?;
+        // A template that only has its projects_dir is a string, make it an inline table in place
+        if let Some(projects_dir) = template.as_value().and_then(|value| value.as_str()) {
+            let mut table = InlineTable::new();
+            table.insert("projects_dir", projects_dir.into());
+            set_value(template, table);
+        }
+        let template = template
+            .as_table_like_mut()
+            .with_context(|| format!("template {name:?} is not a table"))?;
+        set_value(template.entry(key).or_insert(Item::None), new_value);
+        Ok(())
+    }
+
+    /// Writes the changes to the file, if the result is a valid config
+    pub fn save(&self) -> Result<()> {
+        let contents = self.document.to_string();
+        Config::parse_from(&self.path, &contents)
+            .context("the changes would make the config invalid")?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).context("failed creating the config directory")?;
+        }
+        fs::write(&self.path, contents).context("failed writing the config file")
+    }
+
+    /// Returns the `templates` table, adding it if there isn't one
+    fn templates(&mut self) -> Result<&mut dyn toml_edit::TableLike> {
+        let templates = self.document.entry("templates").or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        match templates.as_table_like_mut() {
+            Some(templates) => Ok(templates),
+            None => bail!("`templates` is not a table"),
+        }
+    }
+}
+
+/// Replaces the value of `item`, keeping the comments and whitespace around it
+fn set_value(item: &mut Item, new_value: impl Into<Value>) {
+    let mut new_value = new_value.into();
+    if let Some(old_value) = item.as_value() {
+        *new_value.decor_mut() = old_value.decor().clone();
+    }
+    *item = Item::Value(new_value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_file(contents: &str) -> ConfigFile {
+        ConfigFile {
+            path: PathBuf::from("qk.toml"),
+            document: contents.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_add_template() {
+        let mut file = config_file("# my editor\neditor = 'vi'\n");
+        file.add_template("rs", "~/rs", &[String::from("cargo init")])
+            .unwrap();
+        file.add_template("py", "~/py", &[String::from("a"), String::from("b")])
+            .unwrap();
+        assert_eq!(
+            file.document.to_string(),
+            "# my editor
+editor = 'vi'
+
+[templates.rs]
+projects_dir = \"~/rs\"
+commands = [\"cargo init\"]
+
+[templates.py]
+projects_dir = \"~/py\"
+commands = [
+    \"a\",
+    \"b\",
+]
+"
+        );
+        assert_eq!(
+            file.add_template("rs", "a", &[])
+                .map_err(|err| err.to_string()),
+            Err("template \"rs\" already exists".to_string())
+        );
+    }
+
+    #[test]
+    fn test_remove_template() {
+        let mut file = config_file("[templates]\na = 'a' # first\nb = 'b'\n");
+        file.remove_template("a").unwrap();
+        assert_eq!(file.document.to_string(), "[templates]\nb = 'b'\n");
+        assert_eq!(
+            file.remove_template("a").map_err(|err| err.to_string()),
+            Err("template \"a\" not found".to_string())
+        );
+    }
+
+    #[test]
+    fn test_set() {
+        let mut file = config_file("editor = 'vi' # the best\n\n[templates]\na = '/a'\n");
+        file.set("editor", "nvim", None).unwrap();
+        file.set("shell", "zsh", Some("a")).unwrap();
+        assert_eq!(
+            file.document.to_string(),
+            "editor = \"nvim\" # the best\n\n[templates]\na = { projects_dir = \"/a\", shell = \"zsh\" }\n"
+        );
+        assert!(file.set("projects_dir", "/b", None).is_err());
+        assert!(file.set("commands", "a", None).is_err());
+    }
+}
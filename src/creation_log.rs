@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use clap::crate_name;
+use directories::ProjectDirs;
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Log of the commands executed while creating a project
+///
+/// Contains each command with its output, outcome and duration.
+#[derive(Debug)]
+pub struct CreationLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl CreationLog {
+    /// Creates the log of `project` from `template`, replacing the previous one if any
+    pub fn create(template: &str, project: &str) -> Result<Self> {
+        let path = log_dir()?
+            .join(file_name(template))
+            .join(format!("{}.log", file_name(project)));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = File::create(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `bytes` to the log, ignoring errors so that they don't stop the creation
+    pub fn write(&self, bytes: &[u8]) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.write_all(bytes);
+        }
+    }
+}
+
+/// Returns the directory where the creation logs are stored
+pub fn log_dir() -> Result<PathBuf> {
+    let dirs =
+        ProjectDirs::from("", "", crate_name!()).context("failed finding the home directory")?;
+    let state_dir = dirs.state_dir().unwrap_or_else(|| dirs.data_local_dir());
+    Ok(state_dir.join("logs"))
+}
+
+/// Returns `name` as the name of a single file in the logs directory
+///
+/// Path separators are replaced and `.` or `..` are prefixed, so that a name like `../a` or
+/// `a/b` can't be a path outside of it or in a directory that doesn't exist.
+fn file_name(name: &str) -> String {
+    let name = name.replace(['/', '\\', ':', '\0'], "_");
+    match name.as_str() {
+        "" | "." | ".." => format!("_{name}"),
+        _ => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("my-project"), "my-project");
+        assert_eq!(file_name("../a/b"), ".._a_b");
+        assert_eq!(file_name(".."), "_..");
+        assert_eq!(file_name("C:\\a"), "C__a");
+    }
+}
@@ -0,0 +1,157 @@
+use anyhow::{bail, Result};
+
+/// A function applied to a value in an expression, e.g. `{{name | upper}}`
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Filter {
+    Upper,
+    Lower,
+    /// `my_project`
+    Snake,
+    /// `my-project`
+    Kebab,
+    /// `myProject`
+    Camel,
+    /// `MyProject`
+    Pascal,
+    Trim,
+    /// Quotes the value for the shell, if needed
+    Quote,
+}
+
+impl Filter {
+    /// Names of the filters that can be used in expressions
+    pub const NAMES: &'static [&'static str] = &[
+        "upper", "lower", "snake", "kebab", "camel", "pascal", "trim", "quote",
+    ];
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name {
+            "upper" => Filter::Upper,
+            "lower" => Filter::Lower,
+            "snake" => Filter::Snake,
+            "kebab" => Filter::Kebab,
+            "camel" => Filter::Camel,
+            "pascal" => Filter::Pascal,
+            "trim" => Filter::Trim,
+            "quote" => Filter::Quote,
+            _ => bail!(
+                "unknown filter `{name}`, expected one of: {}",
+                Self::NAMES.join(", ")
+            ),
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::Upper => "upper",
+            Filter::Lower => "lower",
+            Filter::Snake => "snake",
+            Filter::Kebab => "kebab",
+            Filter::Camel => "camel",
+            Filter::Pascal => "pascal",
+            Filter::Trim => "trim",
+            Filter::Quote => "quote",
+        }
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        match self {
+            Filter::Upper => value.to_uppercase(),
+            Filter::Lower => value.to_lowercase(),
+            Filter::Snake => words(value).join("_"),
+            Filter::Kebab => words(value).join("-"),
+            Filter::Camel => words(value)
+                .iter()
+                .enumerate()
+                .map(|(index, word)| match index {
+                    0 => word.clone(),
+                    _ => capitalize(word),
+                })
+                .collect(),
+            Filter::Pascal => words(value).iter().map(|word| capitalize(word)).collect(),
+            Filter::Trim => value.trim().to_string(),
+            Filter::Quote => shlex::try_quote(value)
+                .map(|quoted| quoted.to_string())
+                .unwrap_or_else(|_| value.to_string()),
+        }
+    }
+}
+
+/// Splits `value` in lowercase words, at non alphanumeric characters and at the start of
+/// uppercase words
+///
+/// Example: `myHTTP-server 2` is split in `my`, `http`, `server` and `2`
+fn words(value: &str) -> Vec<String> {
+    let chars: Vec<char> = value.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[index - 1];
+            let next_is_lowercase = chars.get(index + 1).is_some_and(|c| c.is_lowercase());
+            if previous.is_lowercase() || (previous.is_uppercase() && next_is_lowercase) {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        assert_eq!(words("myHTTP-server 2"), vec!["my", "http", "server", "2"]);
+        assert_eq!(words("MyProject"), vec!["my", "project"]);
+        assert_eq!(words("__"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(Filter::Upper.apply("my app"), "MY APP");
+        assert_eq!(Filter::Lower.apply("My App"), "my app");
+        assert_eq!(Filter::Snake.apply("my-app"), "my_app");
+        assert_eq!(Filter::Kebab.apply("MyApp"), "my-app");
+        assert_eq!(Filter::Camel.apply("my_app"), "myApp");
+        assert_eq!(Filter::Camel.apply(""), "");
+        assert_eq!(Filter::Pascal.apply("my app"), "MyApp");
+        assert_eq!(Filter::Trim.apply(" a "), "a");
+        assert_eq!(Filter::Quote.apply("a b"), "'a b'");
+        assert_eq!(Filter::Quote.apply("ab"), "ab");
+    }
+
+    #[test]
+    fn test_from_name() {
+        for name in Filter::NAMES {
+            assert_eq!(Filter::from_name(name).unwrap().name(), *name);
+        }
+        assert_eq!(
+            Filter::from_name("reverse").map_err(|err| err.to_string()),
+            Err(
+                "unknown filter `reverse`, expected one of: upper, lower, snake, kebab, camel, \
+                 pascal, trim, quote"
+                    .to_string()
+            )
+        );
+    }
+}
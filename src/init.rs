@@ -0,0 +1,167 @@
+use crate::config::{CommandConfig, Template};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use std::env;
+use std::io::{BufRead, Write};
+
+/// A toolchain that `qk config init` has a starter template for
+#[derive(Debug, PartialEq, Eq)]
+pub struct Toolchain {
+    /// Name used with `--from`
+    pub name: &'static str,
+    /// Name of the template
+    pub template: &'static str,
+    /// Programs of the toolchain, the first one found in `PATH` replaces `{program}` in commands
+    programs: &'static [&'static str],
+    commands: &'static [&'static str],
+}
+
+pub const TOOLCHAINS: &[Toolchain] = &[
+    Toolchain {
+        name: "cargo",
+        template: "rs",
+        programs: &["cargo"],
+        commands: &["{program} new $QK_PROJECT_NAME #{lib?}"],
+    },
+    Toolchain {
+        name: "npm",
+        template: "js",
+        programs: &["npm"],
+        commands: &["mkdir $QK_PROJECT_NAME && cd $QK_PROJECT_NAME && {program} init -y"],
+    },
+    Toolchain {
+        name: "go",
+        template: "go",
+        programs: &["go"],
+        commands: &[
+            "mkdir $QK_PROJECT_NAME && cd $QK_PROJECT_NAME && {program} mod init #{1:module!}",
+        ],
+    },
+    Toolchain {
+        name: "python",
+        template: "py",
+        programs: &["python3", "python"],
+        commands: &["mkdir $QK_PROJECT_NAME && {program} -m venv $QK_PROJECT_NAME/.venv"],
+    },
+];
+
+impl Toolchain {
+    /// Names of the toolchains, to use with `--from`
+    pub fn names() -> Vec<&'static str> {
+        TOOLCHAINS.iter().map(|toolchain| toolchain.name).collect()
+    }
+
+    pub fn from_name(name: &str) -> Result<&'static Toolchain> {
+        match TOOLCHAINS.iter().find(|toolchain| toolchain.name == name) {
+            Some(toolchain) => Ok(toolchain),
+            None => bail!(
+                "unknown toolchain `{name}`, expected one of: {}",
+                Self::names().join(", ")
+            ),
+        }
+    }
+
+    /// Returns the toolchains with a program in `PATH`
+    pub fn detect() -> Vec<&'static Toolchain> {
+        TOOLCHAINS
+            .iter()
+            .filter(|toolchain| toolchain.find_program().is_some())
+            .collect()
+    }
+
+    /// Returns the starter template for this toolchain
+    pub fn template(&self, projects_dir: &str) -> Template {
+        let program = self.find_program().unwrap_or(self.programs[0]);
+        let commands = self
+            .commands
+            .iter()
+            .map(|command| CommandConfig::Shell(command.replace("{program}", program)))
+            .collect();
+        Template::new(self.template, projects_dir, commands)
+    }
+
+    fn find_program(&self) -> Option<&'static str> {
+        self.programs
+            .iter()
+            .copied()
+            .find(|program| in_path(program))
+    }
+}
+
+/// Returns whether `program` is an executable in one of the directories in `PATH`
+fn in_path(program: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    let file_name = format!("{program}{}", env::consts::EXE_SUFFIX);
+    env::split_paths(&path).any(|dir| dir.join(&file_name).is_file())
+}
+
+/// Prints `question` and reads the answer, which is `default` if it's empty
+///
+/// Fails if the input ends before an answer, instead of taking that as the default.
+pub fn prompt(
+    question: &str,
+    default: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<String> {
+    write!(output, "{question} [{default}]: ")?;
+    output.flush()?;
+    let mut answer = String::new();
+    let read = input
+        .read_line(&mut answer)
+        .context("failed reading the answer")?;
+    ensure!(read > 0, "no answer to {question:?}, the input ended");
+    let answer = answer.trim();
+    Ok(match answer {
+        "" => default.to_string(),
+        _ => answer.to_string(),
+    })
+}
+
+/// Asks a yes or no `question`, yes being the default
+pub fn confirm(question: &str, input: &mut impl BufRead, output: &mut impl Write) -> Result<bool> {
+    let answer = prompt(question, "y", input, output)?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(Toolchain::from_name("go").unwrap().template, "go");
+        assert_eq!(
+            Toolchain::from_name("ruby").map_err(|err| err.to_string()),
+            Err("unknown toolchain `ruby`, expected one of: cargo, npm, go, python".to_string())
+        );
+    }
+
+    #[test]
+    fn test_starter_templates_parse() {
+        for toolchain in TOOLCHAINS {
+            let template = toolchain.template("~/projects");
+            assert_eq!(template.name(), toolchain.template);
+            assert!(template.commands().is_ok(), "{}", toolchain.name);
+        }
+    }
+
+    #[test]
+    fn test_prompt() {
+        let mut output = Vec::new();
+        let answer = prompt("Add it?", "y", &mut "\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answer, "y");
+        assert_eq!(output, b"Add it? [y]: ");
+        let answer = prompt("Dir", "~/a", &mut " ~/b \n".as_bytes(), &mut output).unwrap();
+        assert_eq!(answer, "~/b");
+        assert_eq!(
+            prompt("Dir", "~/a", &mut "".as_bytes(), &mut output).map_err(|err| err.to_string()),
+            Err("no answer to \"Dir\", the input ended".to_string())
+        );
+        assert!(!confirm("Add it?", &mut "no\n".as_bytes(), &mut output).unwrap());
+        assert!(confirm("Add it?", &mut "Y\n".as_bytes(), &mut output).unwrap());
+    }
+}
@@ -1,7 +1,13 @@
+pub mod builtins;
 pub mod commands_parser;
 pub mod config;
+pub mod config_file;
+pub mod creation_log;
+pub mod filters;
+pub mod init;
 pub mod project;
+pub mod runner;
 pub mod utils;
 
-use commands_parser::{Command, Unit};
+use commands_parser::{Command, CommandLine, Unit};
 use config::{Config, Template};
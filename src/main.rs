@@ -4,14 +4,19 @@
 
 mod cmd;
 
-use qk::config::Config;
+use qk::config::{Config, ConfigFormat, Template};
+use qk::config_file::ConfigFile;
+use qk::init::{self, Toolchain};
 use qk::project::Project;
-use qk::utils;
+use qk::utils::{self, Source};
 
-use std::{fs, process};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
 
 use anyhow::Context;
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Result};
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
 use clap::ArgMatches;
 
 fn main() -> Result<()> {
@@ -23,15 +28,31 @@ fn main() -> Result<()> {
 }
 
 fn run() -> Result<()> {
-    let matches = cmd::cmd().get_matches();
-    let config = match matches.get_one::<String>("config") {
-        Some(path) => Config::load_from(path),
-        None => Config::load(),
+    let matches = cmd::get_matches();
+    if let Some(("config", config_matches)) = matches.subcommand() {
+        return handle_config(&matches, config_matches);
     }
-    .context("failed loading config")?;
 
-    if *matches.get_one::<bool>("list-templates").unwrap() {
-        return handle_list_templates(&config);
+    // `requires` doesn't work with --list-templates, since it's always present with its default
+    let list_templates = *matches.get_one::<bool>("list-templates").unwrap();
+    for id in ["long", "tag"] {
+        if !list_templates && matches.value_source(id) == Some(ValueSource::CommandLine) {
+            cmd::cmd()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    format!(
+                        "the following required arguments were not provided:\n  --list-templates\n\n\
+                         --{id} can only be used with --list-templates"
+                    ),
+                )
+                .exit();
+        }
+    }
+
+    let config = load_config(&matches)?;
+
+    if list_templates {
+        return handle_list_templates(&config, &matches);
     }
 
     if *matches.get_one::<bool>("list-projects").unwrap() {
@@ -45,11 +66,37 @@ fn run() -> Result<()> {
     handle_main_operation(&config, &matches)
 }
 
+/// Loads the config with the closest repository-local config merged over it, if it's trusted
+///
+/// The local config and the templates it replaces are reported, since they change what runs.
+fn load_config(matches: &ArgMatches) -> Result<Config> {
+    let mut config = match matches.get_one::<String>("config") {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    }
+    .context("failed loading config")?;
+    let Some(path) = Config::find_local(&env::current_dir()?) else {
+        return Ok(config);
+    };
+    if !config.trusts(&path) {
+        eprintln!(
+            "warning: ignoring the local config {}, its directory isn't in trusted_dirs",
+            path.display()
+        );
+        return Ok(config);
+    }
+    eprintln!("using the local config {}", path.display());
+    for name in config.merge_local(&path)? {
+        eprintln!("warning: the local config replaces the template {name:?}");
+    }
+    Ok(config)
+}
+
 /// Prints the projects from a template
 fn handle_list_projects(config: &Config, matches: &ArgMatches) -> Result<()> {
     let template = matches.get_one::<String>("template").unwrap();
     let template = config
-        .find_template(template)
+        .find_template(template)?
         .context("template not found")?;
 
     let mut items =
@@ -64,32 +111,79 @@ fn handle_list_projects(config: &Config, matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
-/// Prints the templates in the config
-fn handle_list_templates(config: &Config) -> Result<()> {
-    let mut templates = config
+/// Prints the templates in the config, optionally only the ones with some tags or as a table
+fn handle_list_templates(config: &Config, matches: &ArgMatches) -> Result<()> {
+    let tags: Vec<&String> = matches.get_many("tag").unwrap_or_default().collect();
+    let mut templates: Vec<_> = config
         .templates()
-        .keys()
-        .map(|name| name.to_string())
-        .collect::<Vec<_>>();
-    templates.sort();
+        .into_values()
+        .filter(|template| tags.iter().all(|tag| template.tags().contains(tag)))
+        .collect();
+    templates.sort_by(|a, b| a.name().cmp(b.name()));
 
-    if templates.is_empty() {
+    if templates.is_empty() && !tags.is_empty() {
+        bail!("no templates with these tags")
+    } else if templates.is_empty() {
         bail!("no templates yet")
+    } else if *matches.get_one::<bool>("long").unwrap() {
+        print_templates_table(&templates);
     } else {
-        println!("{}", templates.join("\n"));
+        for template in templates {
+            println!("{}", template.name());
+        }
     }
 
     Ok(())
 }
 
+/// Prints the templates as a table, with the columns aligned
+fn print_templates_table(templates: &[Template]) {
+    let header = ["NAME", "DESCRIPTION", "PROJECTS_DIR", "PROJECTS", "ARGS"].map(String::from);
+    let rows: Vec<[String; 5]> = templates
+        .iter()
+        .map(|template| {
+            let projects = utils::list_dir(template.projects_dir()).map_or(0, |dir| dir.len());
+            let args = match template.usage_args() {
+                Ok(args) => args.join(" "),
+                Err(_) => String::from("(invalid commands)"),
+            };
+            [
+                template.name().to_string(),
+                template.description().cloned().unwrap_or_default(),
+                template.projects_dir().display().to_string(),
+                projects.to_string(),
+                args,
+            ]
+        })
+        .collect();
+
+    let rows: Vec<_> = std::iter::once(header).chain(rows).collect();
+    let widths: Vec<usize> = (0..5)
+        .map(|column| {
+            rows.iter()
+                .map(|row| row[column].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
 /// Prints the editor that would open for a template, if any
 fn handle_show_editor(config: &Config, matches: &ArgMatches) -> Result<()> {
     let template = matches.get_one::<String>("template").unwrap();
     let template = config
-        .find_template(template)
+        .find_template(template)?
         .context("template not found")?;
 
-    let editor = utils::get_editor(config, &template, matches);
+    let editor = utils::get_editor(Some(config), Some(&template), matches);
 
     if let Some(editor) = editor {
         println!("{editor}");
@@ -98,20 +192,253 @@ fn handle_show_editor(config: &Config, matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Changes the config file, which doesn't need to be a valid config to be edited
+fn handle_config(matches: &ArgMatches, config_matches: &ArgMatches) -> Result<()> {
+    let path = match matches.get_one::<String>("config") {
+        Some(path) => PathBuf::from(path),
+        None => Config::path()?,
+    };
+    match config_matches.subcommand() {
+        Some(("show", config_matches)) => {
+            let template = config_matches.get_one::<String>("template");
+            return handle_config_show(&path, template.map(String::as_str), matches);
+        }
+        Some(("edit", _)) => return edit_config(&path, matches),
+        Some(("init", config_matches)) => return init_config(&path, config_matches),
+        Some(("schema", _)) => {
+            println!("{}", Config::json_schema());
+            return Ok(());
+        }
+        _ => (),
+    }
+    let mut file = ConfigFile::open(path)?;
+    let get = |matches: &ArgMatches, name: &str| matches.get_one::<String>(name).cloned();
+    match config_matches.subcommand() {
+        Some(("add-template", matches)) => {
+            let commands: Vec<String> = matches
+                .get_many::<String>("command")
+                .unwrap_or_default()
+                .cloned()
+                .collect();
+            file.add_template(
+                &get(matches, "name").unwrap(),
+                &get(matches, "projects-dir").unwrap(),
+                &commands,
+            )?
+        }
+        Some(("remove-template", matches)) => {
+            file.remove_template(&get(matches, "name").unwrap())?
+        }
+        Some(("set", matches)) => file.set(
+            &get(matches, "key").unwrap(),
+            &get(matches, "value").unwrap(),
+            get(matches, "template").as_deref(),
+        )?,
+        _ => unreachable!("config requires a subcommand"),
+    }
+    file.save()
+}
+
+/// Prints the settings that would be used, each one after where it comes from
+fn handle_config_show(path: &Path, template: Option<&str>, matches: &ArgMatches) -> Result<()> {
+    let config = load_config(matches)?;
+    let template = template
+        .map(|name| config.find_template(name)?.context("template not found"))
+        .transpose()?;
+
+    // Settings in the config can come from the repository-local config instead
+    let file = |key: &str| config.origin(key).unwrap_or(path).display().to_string();
+    let origin = |source: Source, key: &str| match source {
+        Source::Cli(option) => format!("command line:{option}"),
+        Source::Template(name) => {
+            format!(
+                "file:{} (templates.{name})",
+                file(&format!("templates.{name}"))
+            )
+        }
+        Source::Config => format!("file:{}", file(key)),
+        Source::Env(var) => format!("env:{var}"),
+        Source::Default => String::from("default"),
+    };
+    let config_source = match matches.value_source("config") {
+        Some(ValueSource::EnvVariable) => Source::Env("QK_CONFIG_PATH"),
+        Some(_) => Source::Cli("--config"),
+        None => Source::Default,
+    };
+    println!("{}\tconfig={}", origin(config_source, ""), path.display());
+    if let Some(local) =
+        Config::find_local(&env::current_dir()?).filter(|local| config.trusts(local))
+    {
+        println!("default\tlocal_config={}", local.display());
+    }
+
+    let (editor, source) = utils::get_editor_with_source(Some(&config), template.as_ref(), matches);
+    println!(
+        "{}\teditor={}",
+        origin(source, "editor"),
+        editor.unwrap_or_default()
+    );
+    let (shell, source) = utils::get_shell_with_source(&config, template.as_ref());
+    println!("{}\tshell={shell}", origin(source, "shell"));
+    if let Some(default_template) = config.default_template()? {
+        println!(
+            "{}\tdefault_template={}",
+            origin(Source::Config, "default_template"),
+            default_template.name()
+        );
+    }
+
+    let templates = match template {
+        Some(template) => vec![template],
+        None => {
+            let mut templates: Vec<_> = config.templates().into_values().collect();
+            templates.sort_by(|a, b| a.name().cmp(b.name()));
+            templates
+        }
+    };
+    for template in templates {
+        let name = template.name();
+        let origin = origin(Source::Template(name.to_string()), "");
+        println!(
+            "{origin}\ttemplates.{name}.projects_dir={}",
+            template.projects_dir().display()
+        );
+        for (index, command) in template.command_configs().iter().enumerate() {
+            let command = toml::Value::try_from(command).context("failed showing a command")?;
+            println!("{origin}\ttemplates.{name}.commands[{index}]={command}");
+        }
+    }
+    Ok(())
+}
+
+/// Opens a copy of the config file in the editor, which replaces the file once it's valid
+fn edit_config(path: &Path, matches: &ArgMatches) -> Result<()> {
+    let config = Config::load_from(path).ok();
+    let editor = utils::get_editor(config.as_ref(), None, matches)
+        .context("no editor found, use --editor or set one in the config")?;
+
+    // The copy keeps the extension, which gives the format of the config
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let copy = match path.extension() {
+        Some(extension) => {
+            path.with_file_name(format!(".{stem}.edit.{}", extension.to_string_lossy()))
+        }
+        None => path.with_file_name(format!(".{stem}.edit")),
+    };
+    if path.exists() {
+        fs::copy(path, &copy).context("failed copying the config file")?;
+    } else {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("failed creating the config directory")?;
+        }
+        fs::write(&copy, "").context("failed creating the config file")?;
+    }
+
+    if let Err(err) = edit_until_valid(&editor, &copy) {
+        let _ = fs::remove_file(&copy);
+        return Err(err);
+    }
+    fs::rename(&copy, path).context("failed saving the config file")
+}
+
+/// Opens `path` in `editor` until it's a valid config or the user gives up
+fn edit_until_valid(editor: &str, path: &Path) -> Result<()> {
+    let (mut stdin, mut stdout) = (io::stdin().lock(), io::stdout());
+    loop {
+        let status = utils::editor_command(editor, path)?
+            .status()
+            .context("failed opening editor")?;
+        ensure!(
+            status.success(),
+            "the editor exited with {status}, the changes were not saved"
+        );
+        let Err(err) = Config::load_from(path) else {
+            return Ok(());
+        };
+        eprintln!("error: the config is invalid: {err:#}");
+        if !init::confirm("Edit it again?", &mut stdin, &mut stdout).unwrap_or(false) {
+            bail!("the config is invalid, the changes were not saved");
+        }
+    }
+}
+
+/// Creates the config file with a starter template for each toolchain
+fn init_config(path: &Path, matches: &ArgMatches) -> Result<()> {
+    let force = *matches.get_one::<bool>("force").unwrap();
+    ensure!(
+        force || !path.exists(),
+        "{} already exists, use --force to overwrite it",
+        path.display()
+    );
+    let toolchains = match matches.get_many::<String>("from") {
+        Some(names) => names
+            .map(|name| Toolchain::from_name(name))
+            .collect::<Result<_>>()?,
+        None => Toolchain::detect(),
+    };
+    ensure!(
+        !toolchains.is_empty(),
+        "no toolchains found, choose the templates to add with --from"
+    );
+    let interactive = *matches.get_one::<bool>("interactive").unwrap();
+
+    let mut templates = Vec::new();
+    let (mut stdin, mut stdout) = (io::stdin().lock(), io::stdout());
+    for toolchain in toolchains {
+        let mut projects_dir = format!("~/projects/{}", toolchain.template);
+        if interactive {
+            let question = format!(
+                "Add template {} for {}?",
+                toolchain.template, toolchain.name
+            );
+            if !init::confirm(&question, &mut stdin, &mut stdout)? {
+                continue;
+            }
+            let question = "Directory of its projects";
+            projects_dir = init::prompt(question, &projects_dir, &mut stdin, &mut stdout)?;
+        }
+        println!("{}: {projects_dir}", toolchain.template);
+        templates.push(toolchain.template(&projects_dir));
+    }
+    ensure!(
+        !templates.is_empty(),
+        "no templates chosen, nothing was written"
+    );
+
+    let contents = ConfigFormat::from_path(path).serialize(&Config::from_templates(templates))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("failed creating the config directory")?;
+    }
+    fs::write(path, contents).context("failed writing the config file")?;
+    println!("created {}", path.display());
+    Ok(())
+}
+
 /// Creates a new project
 fn handle_main_operation(config: &Config, matches: &ArgMatches) -> Result<()> {
     let template = matches.get_one::<String>("template").unwrap();
-    let template = config
-        .find_template(template)
-        .context("template not found")?;
+    // With only one name, it's the project's and the template is the default one
+    let (template, project_name) = match matches.get_one::<String>("project") {
+        Some(project_name) => (config.find_template(template)?, project_name),
+        None => match config.default_template()? {
+            Some(default_template) => (Some(default_template), template),
+            None => cmd::cmd()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "the following required arguments were not provided:\n  <project>\n\n\
+                     Set default_template in the config to create projects without a template",
+                )
+                .exit(),
+        },
+    };
+    let template = template.context("template not found")?;
 
-    let project_name = matches.get_one::<String>("project").unwrap();
     let custom_args = matches
         .get_many::<String>("custom-args")
         .unwrap_or_default()
         .cloned()
         .collect();
-    let editor = utils::get_editor(config, &template, matches);
+    let editor = utils::get_editor(Some(config), Some(&template), matches);
     let shell = utils::get_shell(config, &template);
     let overwrite = *matches.get_one::<bool>("overwrite").unwrap();
 
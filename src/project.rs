@@ -1,11 +1,38 @@
-use crate::{Template, Unit};
+use crate::builtins::Builtins;
+use crate::config::{GroupConfig, TemplateCommand};
+use crate::creation_log::CreationLog;
+use crate::runner::{self, Outcome, RunOptions};
+use crate::{utils, Command as Units, CommandLine, Template, Unit};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::{Command, ExitStatus};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+/// A template command with its custom arguments replaced
+#[derive(Debug)]
+struct ResolvedCommand {
+    line: ResolvedLine,
+    timeout: Option<Duration>,
+    retries: u32,
+    allow_failure: bool,
+    interactive: bool,
+    cwd: PathBuf,
+    name: String,
+    group: Option<usize>,
+}
+
+#[derive(Debug)]
+enum ResolvedLine {
+    Shell(String),
+    Argv(Vec<String>),
+}
 
 #[derive(Debug)]
 pub struct Project<'a> {
@@ -16,6 +43,7 @@ pub struct Project<'a> {
     editor: Option<String>,
     shell: String,
     overwrite: bool,
+    builtins: OnceLock<Builtins>,
 }
 
 impl<'a> Project<'a> {
@@ -35,6 +63,7 @@ impl<'a> Project<'a> {
             overwrite,
             editor,
             shell,
+            builtins: OnceLock::new(),
         }
     }
 
@@ -71,71 +100,357 @@ impl<'a> Project<'a> {
     /// Creates the project
     fn create(&self) -> Result<()> {
         let commands = self.commands()?;
-        for command in commands {
-            let exit_status = self.run_cmd_str(&command, &self.shell)?;
-            if !exit_status.success() {
-                bail!("command exited with non-successful {exit_status}")
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let log = match CreationLog::create(self.template.name(), self.name) {
+            Ok(log) => Some(log),
+            Err(err) => {
+                eprintln!("warning: failed creating the log file: {err:#}");
+                None
+            }
+        };
+
+        let result = self.run_commands(commands, log.as_ref());
+        match (result, log) {
+            (Err(err), Some(log)) => {
+                log.write(format!("error: {err:#}\n").as_bytes());
+                Err(err.context(format!(
+                    "failed creating the project, see the log at {}",
+                    log.path().display()
+                )))
+            }
+            (result, _) => result,
+        }
+    }
+
+    fn run_commands(
+        &self,
+        commands: Vec<ResolvedCommand>,
+        log: Option<&CreationLog>,
+    ) -> Result<()> {
+        for group in commands.chunk_by(|a, b| a.group.is_some() && a.group == b.group) {
+            if group[0].group.is_some() {
+                self.run_parallel(group, log)?;
+            } else {
+                for command in group {
+                    let options = RunOptions {
+                        log,
+                        ..Default::default()
+                    };
+                    self.run_command(command, options)?;
+                }
             }
         }
         Ok(())
     }
 
+    /// Runs `commands` at the same time, stopping all of them as soon as one fails
+    fn run_parallel(&self, commands: &[ResolvedCommand], log: Option<&CreationLog>) -> Result<()> {
+        let cancel = AtomicBool::new(false);
+        let failure = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for command in commands {
+                let prefix = format!("[{}] ", command.name);
+                let (cancel, failure) = (&cancel, &failure);
+                scope.spawn(move || {
+                    let options = RunOptions {
+                        prefix: Some(&prefix),
+                        cancel: Some(cancel),
+                        log,
+                        ..Default::default()
+                    };
+                    if let Err(err) = self.run_command(command, options) {
+                        // Only the first failure is reported, the rest were cancelled by it
+                        if !cancel.swap(true, Ordering::SeqCst) {
+                            *failure.lock().unwrap() = Some((&command.name, err));
+                        }
+                    }
+                });
+            }
+        });
+
+        match failure.into_inner().unwrap() {
+            Some((name, err)) => Err(err.context(format!(
+                "parallel command {name:?} failed, the other commands in its group were stopped"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `command`, retrying it if it fails and it has retries
+    fn run_command(&self, command: &ResolvedCommand, options: RunOptions) -> Result<()> {
+        let options = RunOptions {
+            timeout: command.timeout,
+            interactive: command.interactive,
+            ..options
+        };
+        let mut attempt = 0;
+        loop {
+            let outcome = match &command.line {
+                ResolvedLine::Shell(line) => self.run_cmd_str(line, &self.shell, command, options),
+                ResolvedLine::Argv(args) => self.run_cmd_argv(args, command, options),
+            };
+
+            // A command that couldn't be started is retried and allowed to fail like the others
+            let failure = match outcome {
+                Ok(outcome) if outcome.success() => return Ok(()),
+                Ok(Outcome::Cancelled) => bail!(describe_failure(&Outcome::Cancelled)),
+                Ok(outcome) => anyhow!(describe_failure(&outcome)),
+                Err(err) => err,
+            };
+            if attempt < command.retries {
+                attempt += 1;
+                warn(
+                    &format!("{failure:#}, retrying ({attempt}/{})", command.retries),
+                    options,
+                );
+            } else if command.allow_failure {
+                warn(
+                    &format!("{failure:#}, continuing since it's allowed to fail"),
+                    options,
+                );
+                return Ok(());
+            } else {
+                return Err(failure);
+            }
+        }
+    }
+
     fn open(&self) -> Result<()> {
         if let Some(editor) = &self.editor {
-            Command::new(editor)
-                .arg(&self.dir)
+            utils::editor_command(editor, &self.dir)?
                 .status()
                 .context("failed opening editor")?;
         }
         Ok(())
     }
 
-    fn commands(&self) -> Result<Vec<String>> {
+    fn commands(&self) -> Result<Vec<ResolvedCommand>> {
         let parsed_commands = self.template.commands()?;
-        let set_commands: HashSet<&Unit> = parsed_commands.iter().flatten().collect();
+        // Arguments in the template's `args` table can be used only in expressions or `when`
+        let declared_args: Vec<Unit> = self
+            .template
+            .args()
+            .iter()
+            .map(|(name, arg)| arg.to_unit(name))
+            .collect::<Result<_>>()?;
+        let mut set_commands: HashSet<&Unit> = parsed_commands
+            .iter()
+            .flat_map(TemplateCommand::units)
+            .chain(&declared_args)
+            .collect();
+
+        // Commands can depend on a custom argument that isn't used anywhere, add a flag for it
+        let mut args_by_name: HashMap<&str, &Unit> = set_commands
+            .iter()
+            .filter_map(|unit| Some((unit.name()?, *unit)))
+            .collect();
+        let when_flags: Vec<Unit> = parsed_commands
+            .iter()
+            .filter(|command| {
+                command
+                    .when_name()
+                    .is_some_and(|name| !args_by_name.contains_key(name))
+            })
+            .filter_map(|command| command.when.as_deref())
+            .map(Unit::flag)
+            .collect();
+        for flag in &when_flags {
+            set_commands.insert(flag);
+            args_by_name.insert(flag.name().unwrap(), flag);
+        }
+
         let clap_args = Unit::to_clap_args(set_commands);
-        let matches = self
-            .get_cmd()
-            .args(&clap_args)
-            .get_matches_from(&self.custom_args);
+        let matches = self.get_cmd(clap_args).get_matches_from(&self.custom_args);
+        let builtins = self.builtins();
 
         let mut commands = Vec::new();
 
         for command in &parsed_commands {
-            let mut str_command = String::new();
-            for unit in command {
-                if let Some(unit) = unit.to_value(&matches) {
-                    str_command.push_str(&unit);
+            if let Some(when) = command.when_name() {
+                if !args_by_name[when].is_set(&matches) {
+                    continue;
                 }
             }
-            commands.push(str_command)
+            let line = match &command.line {
+                CommandLine::Shell(units) => ResolvedLine::Shell(
+                    resolve_units(units, &matches, builtins).unwrap_or_default(),
+                ),
+                CommandLine::Argv(args) => {
+                    // Arguments made only of custom args without a value are left out,
+                    // so that e.g. an unset flag doesn't become an empty argument, and
+                    // arguments made only of a repeated custom arg become one per value.
+                    // Any other argument is kept, even if it's empty.
+                    let args: Vec<String> = args
+                        .iter()
+                        .flat_map(|units| match units.as_slice() {
+                            [unit] if unit.is_repeated() => {
+                                unit.to_values(&matches).unwrap_or_default()
+                            }
+                            _ => {
+                                let resolved = resolve_units(units, &matches, builtins);
+                                match only_custom_args(units) {
+                                    true => resolved.into_iter().collect(),
+                                    false => vec![resolved.unwrap_or_default()],
+                                }
+                            }
+                        })
+                        .collect();
+                    if args.is_empty() {
+                        bail!("argv command resolved to no arguments")
+                    }
+                    ResolvedLine::Argv(args)
+                }
+            };
+            let name = command.name.clone().unwrap_or_else(|| match &line {
+                ResolvedLine::Shell(line) => line.lines().next().unwrap_or_default().to_string(),
+                ResolvedLine::Argv(args) => args.join(" "),
+            });
+            let cwd = command
+                .cwd
+                .as_ref()
+                .and_then(|cwd| resolve_units(cwd, &matches, builtins))
+                .map(|cwd| self.template.projects_dir().join(cwd))
+                .unwrap_or_else(|| self.template.projects_dir().to_path_buf());
+            commands.push(ResolvedCommand {
+                line,
+                timeout: command.timeout,
+                retries: command.retries,
+                allow_failure: command.allow_failure,
+                interactive: command.interactive,
+                cwd,
+                name,
+                group: command.group,
+            })
         }
         Ok(commands)
     }
 
-    fn get_cmd(&self) -> clap::Command {
-        clap::Command::new(self.template.name().to_string())
+    /// Returns the values of the built-in variables, available with `#{@name}`
+    fn builtins(&self) -> &Builtins {
+        self.builtins.get_or_init(|| {
+            Builtins::new(
+                self.template.name(),
+                self.name,
+                &self.dir,
+                self.template.projects_dir(),
+            )
+        })
+    }
+
+    fn get_cmd(&self, args: Vec<clap::Arg>) -> clap::Command {
+        // Unnamed, so that the usage in errors lists only the template's arguments
+        let mut cmd = clap::Command::new("")
             .no_binary_name(true)
             .disable_version_flag(true)
+            .args(args)
+            .groups(
+                self.template
+                    .groups()
+                    .iter()
+                    .map(GroupConfig::to_clap_group),
+            );
+        for (name, arg) in self.template.args() {
+            cmd = cmd.mut_arg(name, |clap_arg| arg.configure(clap_arg));
+        }
+        for (arg, required) in self.template.requires() {
+            cmd = cmd.mut_arg(arg, |arg| {
+                required.iter().fold(arg, |arg, id| arg.requires(id))
+            });
+        }
+        for (arg, conflicts) in self.template.conflicts() {
+            cmd = cmd.mut_arg(arg, |arg| arg.conflicts_with_all(conflicts));
+        }
+        cmd
+    }
+
+    fn run_cmd_str(
+        &self,
+        command: &str,
+        shell: &str,
+        command_options: &ResolvedCommand,
+        options: RunOptions,
+    ) -> Result<Outcome> {
+        let line = command
+            .lines()
+            .map(|line| format!("$ {}", line))
+            .collect::<Vec<String>>()
+            .join("\n");
+        let mut command_process = Command::new(shell);
+        command_process.arg("-c").arg(command);
+        self.run(command_process, &line, command_options, options)
+    }
+
+    fn run_cmd_argv(
+        &self,
+        args: &[String],
+        command_options: &ResolvedCommand,
+        options: RunOptions,
+    ) -> Result<Outcome> {
+        let line =
+            shlex::try_join(args.iter().map(String::as_str)).unwrap_or_else(|_| args.join(" "));
+        let mut command_process = Command::new(&args[0]);
+        command_process.args(&args[1..]);
+        self.run(
+            command_process,
+            &format!("$ {}", line),
+            command_options,
+            options,
+        )
     }
 
-    fn run_cmd_str(&self, command: &str, shell: &str) -> Result<ExitStatus> {
-        println!(
-            "{}",
-            command
-                .lines()
-                .map(|line| format!("$ {}", line))
-                .collect::<Vec<String>>()
-                .join("\n")
-        );
-        Command::new(shell)
-            .arg("-c")
-            .arg(command)
-            .env("QK_PROJECTS_DIR", self.template.projects_dir())
-            .env("QK_PROJECT_DIR", &self.dir)
-            .env("QK_PROJECT_NAME", self.name)
-            .current_dir(self.template.projects_dir())
-            .status()
-            .context("failed running command")
+    fn run(
+        &self,
+        mut command: Command,
+        line: &str,
+        command_options: &ResolvedCommand,
+        options: RunOptions,
+    ) -> Result<Outcome> {
+        command
+            .envs(self.template.env())
+            .envs(self.builtins().env_vars(line))
+            .current_dir(&command_options.cwd);
+        runner::run(&mut command, line, options).context("failed running command")
+    }
+}
+
+fn describe_failure(outcome: &Outcome) -> String {
+    match outcome {
+        Outcome::Exited(status) => format!("command exited with non-successful {status}"),
+        Outcome::TimedOut(timeout) => format!("command timed out after {timeout:?}"),
+        Outcome::Cancelled => String::from("command was cancelled"),
+    }
+}
+
+/// Prints a warning, also writing it to the log if any
+fn warn(message: &str, options: RunOptions) {
+    let message = format!("{}warning: {message}", options.prefix.unwrap_or_default());
+    eprintln!("{message}");
+    if let Some(log) = options.log {
+        log.write(format!("{message}\n").as_bytes());
+    }
+}
+
+/// Returns whether `units` has custom args and nothing else, like text or built-in variables
+fn only_custom_args(units: &Units) -> bool {
+    !units.is_empty()
+        && units
+            .iter()
+            .all(|unit| !matches!(unit, Unit::Text(_) | Unit::Builtin(_)))
+}
+
+/// Concatenates the values of `units`
+///
+/// Returns `None` if there were no text units and none of the custom arguments had a value.
+fn resolve_units(units: &Units, matches: &clap::ArgMatches, builtins: &Builtins) -> Option<String> {
+    let mut resolved = None;
+    for unit in units {
+        if let Some(value) = unit.to_value(matches, builtins) {
+            resolved.get_or_insert_with(String::new).push_str(&value);
+        }
     }
+    resolved
 }
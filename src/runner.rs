@@ -0,0 +1,415 @@
+use crate::creation_log::CreationLog;
+
+use std::fmt;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked for having exited or timed out
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for more output after a command exits, in case its children still hold the
+/// output open
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_millis(100);
+
+/// How a command finished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Exited(ExitStatus),
+    TimedOut(Duration),
+    Cancelled,
+}
+
+impl Outcome {
+    pub fn success(&self) -> bool {
+        matches!(self, Outcome::Exited(status) if status.success())
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Exited(status) => write!(f, "{status}"),
+            Outcome::TimedOut(timeout) => write!(f, "timed out after {timeout:?}"),
+            Outcome::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunOptions<'a> {
+    /// Time after which the command is killed
+    pub timeout: Option<Duration>,
+
+    /// Added at the beginning of each line of output, to tell apart commands running at the
+    /// same time
+    pub prefix: Option<&'a str>,
+
+    /// The command is killed when this becomes `true`
+    pub cancel: Option<&'a AtomicBool>,
+
+    /// Where the command, its output, outcome and duration are written to
+    pub log: Option<&'a CreationLog>,
+
+    /// The output goes straight to the terminal instead of being copied to the log
+    pub interactive: bool,
+}
+
+enum Output {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    Closed,
+}
+
+/// Runs `command` until it exits, times out or is cancelled, killing it in the last two cases
+///
+/// `line` is how the command is shown to the user, it's printed before running it. If there's a
+/// log or a prefix, the output of the command is copied to the terminal instead of inherited,
+/// unless the command is interactive.
+pub fn run(command: &mut Command, line: &str, options: RunOptions) -> io::Result<Outcome> {
+    let mut writer = Writer::new(options);
+    writer.write(Stream::Stdout, format!("{line}\n").as_bytes());
+    writer.flush();
+
+    let start = Instant::now();
+    let outcome = spawn_and_wait(command, options, &mut writer);
+    writer.flush();
+
+    if let Some(log) = options.log {
+        let footer = match &outcome {
+            Ok(outcome) => format!("[{outcome}, took {:?}]\n\n", start.elapsed()),
+            Err(err) => format!("[failed running command: {err}]\n\n"),
+        };
+        log.write(footer.as_bytes());
+    }
+    outcome
+}
+
+fn spawn_and_wait(
+    command: &mut Command,
+    options: RunOptions,
+    writer: &mut Writer,
+) -> io::Result<Outcome> {
+    let tee = options.prefix.is_some() || (options.log.is_some() && !options.interactive);
+    if tee {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    } else if let Some(log) = options.log {
+        log.write(b"[the output of interactive commands isn't logged]\n");
+    }
+    // A command that may be killed gets its own process group, so that the processes it starts
+    // are killed with it. Only then, since it stops being in the foreground group of the terminal.
+    let own_group = cfg!(unix) && (options.timeout.is_some() || options.cancel.is_some());
+    // A command running alone gets the terminal back, so that it can read from it and Ctrl-C
+    // stops it. Commands running at the same time can't share it, so they can't read from it.
+    let terminal = io::stdin().is_terminal();
+    #[cfg(unix)]
+    let foreground = own_group && terminal && options.cancel.is_none();
+    if own_group && terminal && options.cancel.is_some() {
+        command.stdin(Stdio::null());
+    }
+    #[cfg(unix)]
+    if own_group {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+        if foreground {
+            // SAFETY: the closure only calls async-signal-safe functions
+            unsafe {
+                command.pre_exec(|| {
+                    process_group::give_terminal(libc::getpgrp());
+                    Ok(())
+                });
+            }
+        }
+    }
+    let mut child = command.spawn()?;
+    #[cfg(unix)]
+    let _group = own_group.then(|| process_group::ProcessGroup::new(&child, foreground));
+
+    let (sender, receiver) = mpsc::channel();
+    let mut open_outputs = 0;
+    if let Some(stdout) = child.stdout.take() {
+        spawn_reader(stdout, sender.clone(), Output::Stdout);
+        open_outputs += 1;
+    }
+    if let Some(stderr) = child.stderr.take() {
+        spawn_reader(stderr, sender.clone(), Output::Stderr);
+        open_outputs += 1;
+    }
+    drop(sender);
+
+    let start = Instant::now();
+    let outcome = loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Output::Stdout(bytes)) => writer.write(Stream::Stdout, &bytes),
+            Ok(Output::Stderr(bytes)) => writer.write(Stream::Stderr, &bytes),
+            Ok(Output::Closed) => open_outputs -= 1,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(POLL_INTERVAL),
+        }
+
+        if let Some(status) = child.try_wait()? {
+            // A command stopped by a signal, like the one of Ctrl-C, takes the processes it
+            // started with it, since those in the background ignore that signal
+            #[cfg(unix)]
+            if own_group && std::os::unix::process::ExitStatusExt::signal(&status).is_some() {
+                let _ = kill_group(&mut child);
+            }
+            break Outcome::Exited(status);
+        }
+
+        if let Some(timeout) = options
+            .timeout
+            .filter(|timeout| start.elapsed() >= *timeout)
+        {
+            kill(&mut child, own_group)?;
+            break Outcome::TimedOut(timeout);
+        }
+
+        if options
+            .cancel
+            .is_some_and(|cancel| cancel.load(Ordering::SeqCst))
+        {
+            kill(&mut child, own_group)?;
+            break Outcome::Cancelled;
+        }
+    };
+
+    while open_outputs > 0 {
+        match receiver.recv_timeout(OUTPUT_GRACE_PERIOD) {
+            Ok(Output::Stdout(bytes)) => writer.write(Stream::Stdout, &bytes),
+            Ok(Output::Stderr(bytes)) => writer.write(Stream::Stderr, &bytes),
+            Ok(Output::Closed) => open_outputs -= 1,
+            Err(_) => break,
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// Reads `reader` in another thread, sending what's read through `sender`
+fn spawn_reader(
+    mut reader: impl Read + Send + 'static,
+    sender: Sender<Output>,
+    output: fn(Vec<u8>) -> Output,
+) {
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        while let Ok(read @ 1..) = reader.read(&mut buf) {
+            if sender.send(output(buf[..read].to_vec())).is_err() {
+                return;
+            }
+        }
+        let _ = sender.send(Output::Closed);
+    });
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Writes the output of a command to the terminal and the log
+///
+/// With a prefix, only whole lines are written so that each one can be prefixed.
+struct Writer<'a> {
+    prefix: Option<&'a str>,
+    log: Option<&'a CreationLog>,
+    pending_stdout: Vec<u8>,
+    pending_stderr: Vec<u8>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(options: RunOptions<'a>) -> Self {
+        Self {
+            prefix: options.prefix,
+            log: options.log,
+            pending_stdout: Vec::new(),
+            pending_stderr: Vec::new(),
+        }
+    }
+
+    fn write(&mut self, stream: Stream, bytes: &[u8]) {
+        let Some(prefix) = self.prefix else {
+            self.write_raw(stream, bytes);
+            return;
+        };
+
+        let pending = match stream {
+            Stream::Stdout => &mut self.pending_stdout,
+            Stream::Stderr => &mut self.pending_stderr,
+        };
+        pending.extend_from_slice(bytes);
+        let Some(end) = pending.iter().rposition(|byte| *byte == b'\n') else {
+            return;
+        };
+        let lines: Vec<u8> = pending.drain(..=end).collect();
+        let prefixed = prefix_lines(prefix, &lines);
+        self.write_raw(stream, &prefixed);
+    }
+
+    /// Writes what's left of incomplete lines
+    fn flush(&mut self) {
+        for stream in [Stream::Stdout, Stream::Stderr] {
+            let pending = match stream {
+                Stream::Stdout => &mut self.pending_stdout,
+                Stream::Stderr => &mut self.pending_stderr,
+            };
+            if !pending.is_empty() {
+                pending.push(b'\n');
+                self.write(stream, &[]);
+            }
+        }
+    }
+
+    fn write_raw(&self, stream: Stream, bytes: &[u8]) {
+        match stream {
+            Stream::Stdout => {
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(bytes).and_then(|_| stdout.flush());
+            }
+            Stream::Stderr => {
+                let _ = io::stderr().write_all(bytes);
+            }
+        }
+        if let Some(log) = self.log {
+            log.write(bytes);
+        }
+    }
+}
+
+fn prefix_lines(prefix: &str, lines: &[u8]) -> Vec<u8> {
+    let mut prefixed = Vec::with_capacity(lines.len());
+    for line in lines.split_inclusive(|byte| *byte == b'\n') {
+        prefixed.extend_from_slice(prefix.as_bytes());
+        prefixed.extend_from_slice(line);
+    }
+    prefixed
+}
+
+/// Kills `child`, and every process in its group if it has its own
+fn kill(child: &mut Child, own_group: bool) -> io::Result<()> {
+    match own_group {
+        true => kill_group(child)?,
+        false => child.kill()?,
+    }
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn kill_group(child: &mut Child) -> io::Result<()> {
+    // The id of the group is the id of the child, since it was created with `process_group(0)`
+    let group = libc::pid_t::try_from(child.id()).map_err(io::Error::other)?;
+    // SAFETY: killpg only sends a signal, it doesn't touch any memory
+    if unsafe { libc::killpg(group, libc::SIGKILL) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn kill_group(child: &mut Child) -> io::Result<()> {
+    child.kill()
+}
+
+/// Process groups of the running commands, killed if qk is stopped by a signal
+///
+/// The commands in their own group don't get the signals sent to the foreground group of the
+/// terminal, like the one of Ctrl-C, so they would be left running without qk.
+#[cfg(unix)]
+mod process_group {
+    use std::process::Child;
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::sync::Once;
+
+    /// Groups beyond this many at the same time aren't killed when qk is stopped
+    const MAX_GROUPS: usize = 256;
+
+    /// Ids of the running groups, zero in the free slots
+    static GROUPS: [AtomicI32; MAX_GROUPS] = [const { AtomicI32::new(0) }; MAX_GROUPS];
+
+    static INSTALL_HANDLER: Once = Once::new();
+
+    /// The group of a running command, forgotten when dropped
+    pub struct ProcessGroup {
+        slot: Option<usize>,
+        foreground: bool,
+    }
+
+    impl ProcessGroup {
+        /// Tracks the group of `child`, giving it the terminal if it's in the `foreground`
+        pub fn new(child: &Child, foreground: bool) -> Self {
+            INSTALL_HANDLER.call_once(install_handler);
+            // The id of the group is the id of the child, since it was created with
+            // `process_group(0)`
+            let id = child.id() as libc::pid_t;
+            let slot = GROUPS.iter().position(|group| {
+                group
+                    .compare_exchange(0, id, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+            });
+            if foreground {
+                // Also done by the child, whichever happens first, so that it has the terminal
+                // before it runs
+                give_terminal(id);
+            }
+            Self { slot, foreground }
+        }
+    }
+
+    impl Drop for ProcessGroup {
+        fn drop(&mut self) {
+            if let Some(slot) = self.slot {
+                GROUPS[slot].store(0, Ordering::SeqCst);
+            }
+            if self.foreground {
+                // SAFETY: getpgrp can't fail and doesn't touch any memory
+                give_terminal(unsafe { libc::getpgrp() });
+            }
+        }
+    }
+
+    /// Makes `group` the foreground group of the terminal in stdin
+    ///
+    /// Only calls async-signal-safe functions, so that it can be used after forking.
+    pub fn give_terminal(group: libc::pid_t) {
+        // SAFETY: these only change the disposition of SIGTTOU and the foreground group, which a
+        // process in the background is stopped for unless SIGTTOU is ignored
+        unsafe {
+            let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+            libc::tcsetpgrp(libc::STDIN_FILENO, group);
+            libc::signal(libc::SIGTTOU, previous);
+        }
+    }
+
+    fn install_handler() {
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            // SAFETY: the handler only calls async-signal-safe functions
+            unsafe {
+                libc::signal(signal, handle_signal as *const () as libc::sighandler_t);
+            }
+        }
+    }
+
+    /// Kills the running groups, then lets `signal` stop qk as it would have without a handler
+    extern "C" fn handle_signal(signal: libc::c_int) {
+        for group in &GROUPS {
+            let id = group.load(Ordering::SeqCst);
+            if id != 0 {
+                // SAFETY: killpg, signal and raise are async-signal-safe
+                unsafe {
+                    libc::killpg(id, libc::SIGKILL);
+                }
+            }
+        }
+        // SAFETY: see above
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+            libc::raise(signal);
+        }
+    }
+}
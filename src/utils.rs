@@ -1,7 +1,10 @@
 use crate::{Config, Template};
-use anyhow::Result;
+use anyhow::{anyhow, bail, Context, Result};
 use clap::ArgMatches;
-use std::{env, fs, path::Path};
+use std::{env, fs, path::Path, process::Command, time::Duration};
+
+/// Placeholder in the editor that gets replaced by the path to open
+const EDITOR_PATH_PLACEHOLDER: &str = "{dir}";
 
 pub fn list_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
     let read_dir = fs::read_dir(dir)?;
@@ -19,45 +22,358 @@ pub fn list_dir(dir: impl AsRef<Path>) -> Result<Vec<String>> {
     Ok(items)
 }
 
-pub fn get_editor(config: &Config, template: &Template, matches: &ArgMatches) -> Option<String> {
-    let mut editor = matches.get_one("editor").cloned();
+/// Where the value of a setting comes from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Source {
+    /// A command line option
+    Cli(&'static str),
+    /// The template with this name in the config
+    Template(String),
+    /// The beginning of the config, for every template
+    Config,
+    /// An environment variable
+    Env(&'static str),
+    /// None of the others, qk's default
+    Default,
+}
+
+pub fn get_editor(
+    config: Option<&Config>,
+    template: Option<&Template>,
+    matches: &ArgMatches,
+) -> Option<String> {
+    get_editor_with_source(config, template, matches).0
+}
+
+/// Returns the editor like [`get_editor`] and where it comes from
+pub fn get_editor_with_source(
+    config: Option<&Config>,
+    template: Option<&Template>,
+    matches: &ArgMatches,
+) -> (Option<String>, Source) {
+    let mut editor = matches
+        .get_one::<String>("editor")
+        .map(|editor| (editor.clone(), Source::Cli("--editor")));
 
     if editor.is_none() {
-        editor = template.editor().cloned();
+        editor = template.and_then(|template| {
+            let source = Source::Template(template.name().to_string());
+            Some((template.editor()?.clone(), source))
+        });
     }
 
     if editor.is_none() {
-        editor = config.editor().cloned();
+        editor = config
+            .and_then(Config::editor)
+            .map(|editor| (editor.clone(), Source::Config));
     }
 
     if editor.is_none() {
-        editor = env::var("VISUAL").ok()
+        editor = env::var("VISUAL")
+            .ok()
+            .map(|editor| (editor, Source::Env("VISUAL")))
     }
 
     if editor.is_none() {
-        editor = env::var("EDITOR").ok()
+        editor = env::var("EDITOR")
+            .ok()
+            .map(|editor| (editor, Source::Env("EDITOR")))
     }
 
-    // If editor is "", set editor to None
-    if let Some(true) = editor.as_ref().map(|editor| editor.is_empty()) {
-        editor = None
+    match editor {
+        // If editor is "", set editor to None
+        Some((editor, source)) => (Some(editor).filter(|editor| !editor.is_empty()), source),
+        None => (None, Source::Default),
     }
-
-    editor
 }
 
 pub fn get_shell(config: &Config, template: &Template) -> String {
-    if let Some(shell) = template.shell() {
-        return shell.clone();
+    get_shell_with_source(config, Some(template)).0
+}
+
+/// Returns the shell like [`get_shell`] and where it comes from
+pub fn get_shell_with_source(config: &Config, template: Option<&Template>) -> (String, Source) {
+    if let Some(template) = template {
+        if let Some(shell) = template.shell() {
+            return (shell.clone(), Source::Template(template.name().to_string()));
+        }
     }
 
     if let Some(shell) = config.shell() {
-        return shell.clone();
+        return (shell.clone(), Source::Config);
     }
 
     #[cfg(unix)]
-    return env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+    return match env::var("SHELL") {
+        Ok(shell) => (shell, Source::Env("SHELL")),
+        Err(_) => ("sh".to_string(), Source::Default),
+    };
+
+    #[cfg(windows)]
+    return ("PowerShell.exe".to_string(), Source::Default);
+}
 
+/// Builds the command that opens `path` in `editor`
+///
+/// The editor is split into arguments, like a shell would on Unix and like Windows programs do on
+/// Windows (see [`split_windows`]). Every `{dir}` in it is replaced by `path`, if there is none
+/// `path` is passed as the last argument.
+pub fn editor_command(editor: &str, path: &Path) -> Result<Command> {
+    // Allow paths to editors with spaces, like `C:\Program Files\...`
+    if Path::new(editor).is_file() {
+        let mut command = Command::new(editor);
+        command.arg(path);
+        return Ok(command);
+    }
+
+    #[cfg(unix)]
+    let args = shlex::split(editor).context("failed parsing the editor")?;
     #[cfg(windows)]
-    return "PowerShell.exe".to_string();
+    let args = split_windows(editor);
+    let Some((program, args)) = args.split_first() else {
+        bail!("the editor is empty")
+    };
+
+    let path = path.to_string_lossy();
+    let has_placeholder = args.iter().any(|arg| arg.contains(EDITOR_PATH_PLACEHOLDER));
+
+    let mut command = Command::new(program.replace(EDITOR_PATH_PLACEHOLDER, &path));
+    command.args(
+        args.iter()
+            .map(|arg| arg.replace(EDITOR_PATH_PLACEHOLDER, &path)),
+    );
+    if !has_placeholder {
+        command.arg(path.as_ref());
+    }
+    Ok(command)
+}
+
+/// Splits `line` into arguments like Windows programs do
+///
+/// Arguments are separated by whitespace and can be quoted with `"`. Backslashes are only special
+/// before a `"`, so paths like `C:\Users\me\vim.exe` are kept as they are. Like Windows does, if
+/// `line` doesn't start with a quote, the program is the shortest part of it up to a space that is
+/// a file, so `C:\Program Files\Vim\vim.exe -p` works without quotes.
+#[cfg(any(windows, test))]
+fn split_windows(line: &str) -> Vec<String> {
+    let line = line.trim_start();
+    let program_end = match line.starts_with('"') {
+        true => None,
+        false => line
+            .match_indices(char::is_whitespace)
+            .map(|(end, _)| end)
+            .find(|&end| Path::new(&line[..end]).is_file()),
+    };
+    let (mut args, rest) = match program_end {
+        Some(end) => (vec![line[..end].to_string()], &line[end..]),
+        None => (Vec::new(), line),
+    };
+
+    let mut arg: Option<String> = None;
+    let mut quoted = false;
+    let mut backslashes = 0;
+    for c in rest.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c.is_whitespace() && !quoted && backslashes == 0 {
+            args.extend(arg.take());
+            continue;
+        }
+        let current = arg.get_or_insert_with(String::new);
+        if c == '"' {
+            current.extend(std::iter::repeat_n('\\', backslashes / 2));
+            match backslashes % 2 {
+                0 => quoted = !quoted,
+                _ => current.push('"'),
+            }
+        } else {
+            current.extend(std::iter::repeat_n('\\', backslashes));
+            if c.is_whitespace() && !quoted {
+                args.push(arg.take().unwrap());
+            } else {
+                current.push(c);
+            }
+        }
+        backslashes = 0;
+    }
+    if backslashes > 0 {
+        arg.get_or_insert_with(String::new)
+            .extend(std::iter::repeat_n('\\', backslashes));
+    }
+    args.extend(arg);
+    args
+}
+
+/// Expands `~` and the environment variables `$VAR` and `${VAR}` in `path`
+pub fn expand_path(path: &str) -> Result<String> {
+    expand_path_with(path, home_dir().as_deref(), env_var)
+}
+
+/// Expands `~` to `home` and the variables `$VAR` and `${VAR}` to their value in `var` in `path`
+pub fn expand_path_with(
+    path: &str,
+    home: Option<&str>,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<String> {
+    match shellexpand::full_with_context(path, || home, |name| var(name).map(Some).ok_or(())) {
+        Ok(expanded) => Ok(expanded.into_owned()),
+        Err(err) => bail!(
+            "failed expanding path {path:?}, environment variable {} is not set",
+            err.var_name
+        ),
+    }
+}
+
+/// Expands the variables `$VAR` and `${VAR}` to their value in `var` in `text`, but not `~`
+pub fn expand_env(text: &str, var: impl Fn(&str) -> Option<String>) -> Result<String> {
+    match shellexpand::env_with_context(text, |name| var(name).map(Some).ok_or(())) {
+        Ok(expanded) => Ok(expanded.into_owned()),
+        Err(err) => bail!(
+            "failed expanding {text:?}, environment variable {} is not set",
+            err.var_name
+        ),
+    }
+}
+
+/// Returns the home directory of the current user, that `~` expands to
+pub fn home_dir() -> Option<String> {
+    let dirs = directories::BaseDirs::new()?;
+    dirs.home_dir().to_str().map(str::to_string)
+}
+
+/// Returns the value of the environment variable `name`, if it's set
+pub fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
+/// Parses a duration like `500ms`, `30s`, `5m` or `1h30m`
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let mut duration = Duration::ZERO;
+    let mut rest = input.trim();
+    if rest.is_empty() {
+        bail!("empty duration");
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            bail!("invalid duration {input:?}, expected a number");
+        }
+        let too_long = || anyhow!("invalid duration {input:?}, it's too long");
+        let number: u64 = rest[..digits].parse().map_err(|_| too_long())?;
+        rest = &rest[digits..];
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let seconds_in_unit = match &rest[..unit] {
+            "ms" => None,
+            "s" => Some(1),
+            "m" => Some(60),
+            "h" => Some(60 * 60),
+            unit => bail!("invalid duration {input:?}, unknown unit {unit:?}"),
+        };
+        let part = match seconds_in_unit {
+            None => Duration::from_millis(number),
+            Some(seconds) => Duration::from_secs(number.checked_mul(seconds).ok_or_else(too_long)?),
+        };
+        duration = duration.checked_add(part).ok_or_else(too_long)?;
+        rest = &rest[unit..];
+    }
+
+    Ok(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_editor_command_appends_path() {
+        let command = editor_command("code --new-window", Path::new("/a b/c")).unwrap();
+        assert_eq!(args(&command), vec!["code", "--new-window", "/a b/c"]);
+    }
+
+    #[test]
+    fn test_editor_command_with_placeholder() {
+        let command =
+            editor_command("nvim +'cd {dir}' {dir}/README.md", Path::new("/a/b")).unwrap();
+        assert_eq!(args(&command), vec!["nvim", "+cd /a/b", "/a/b/README.md"]);
+    }
+
+    #[test]
+    fn test_split_windows() {
+        assert_eq!(
+            split_windows(r#"C:\Tools\vim.exe -p"#),
+            vec![r"C:\Tools\vim.exe", "-p"]
+        );
+        assert_eq!(
+            split_windows(r#""C:\Program Files\Vim\vim.exe" -p  'a'"#),
+            vec![r"C:\Program Files\Vim\vim.exe", "-p", "'a'"]
+        );
+        assert_eq!(
+            split_windows(r#"a\"b "c\\" d\\ e\"#),
+            vec![r#"a"b"#, r"c\", r"d\\", r"e\"]
+        );
+        assert_eq!(split_windows(r#"a "" b"#), vec!["a", "", "b"]);
+
+        let temp = assert_fs::TempDir::new().unwrap();
+        let program = temp.path().join("Program Files").join("vim");
+        fs::create_dir_all(program.parent().unwrap()).unwrap();
+        fs::write(&program, "").unwrap();
+        let program = program.to_string_lossy();
+        assert_eq!(
+            split_windows(&format!("{program} -p {{dir}}")),
+            vec![program.as_ref(), "-p", "{dir}"]
+        );
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let var = |name: &str| (name == "QK_TEST_EXPAND_PATH").then(|| "b".to_string());
+        let expand = |path| expand_path_with(path, Some("/home/me"), var);
+        assert_eq!(expand("/a/$QK_TEST_EXPAND_PATH").unwrap(), "/a/b");
+        assert_eq!(expand("/a/${QK_TEST_EXPAND_PATH}c").unwrap(), "/a/bc");
+        assert_eq!(expand("~/a").unwrap(), "/home/me/a");
+        assert_eq!(expand("/a/~").unwrap(), "/a/~");
+        assert!(expand("/a/$QK_TEST_EXPAND_PATH_UNSET").is_err());
+        assert_eq!(expand_env("~/$QK_TEST_EXPAND_PATH", var).unwrap(), "~/b");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("5 minutes").is_err());
+        assert_eq!(
+            parse_duration("999999999999999999h")
+                .unwrap_err()
+                .to_string(),
+            "invalid duration \"999999999999999999h\", it's too long"
+        );
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+        assert!(parse_duration("99999999999999999999999s").is_err());
+    }
+
+    #[test]
+    fn test_editor_command_invalid() {
+        assert!(editor_command("code 'unclosed", Path::new("/a")).is_err());
+        assert!(editor_command("  ", Path::new("/a")).is_err());
+    }
 }
@@ -0,0 +1,268 @@
+use std::fs;
+
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn test_config_add_set_and_remove_template() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+    fs::write(
+        config_path,
+        "\
+# Editor for every template
+editor = 'vi' # for now
+
+[templates]
+# Old projects
+old = '/old'
+",
+    )
+    .unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_path)
+            .arg("config")
+            .args(args)
+            .assert()
+    };
+
+    qk(&[
+        "add-template",
+        "rs",
+        "--projects-dir",
+        "~/projects/rs",
+        "--command",
+        "cargo init",
+        "--command",
+        "git add .",
+    ])
+    .success()
+    .stdout("")
+    .stderr("");
+    qk(&["set", "editor", "nvim"]).success();
+    qk(&["set", "shell", "zsh", "--template", "rs"]).success();
+    qk(&["set", "projects_dir", "/older", "-t", "old"]).success();
+
+    config_file.assert(
+        "\
+# Editor for every template
+editor = \"nvim\" # for now
+
+[templates]
+# Old projects
+old = { projects_dir = \"/older\" }
+
+[templates.rs]
+projects_dir = \"~/projects/rs\"
+commands = [
+    \"cargo init\",
+    \"git add .\",
+]
+shell = \"zsh\"
+",
+    );
+
+    qk(&["remove-template", "old"]).success();
+    config_file.assert(predicate::str::contains("old").not());
+
+    qk(&["remove-template", "old"])
+        .failure()
+        .stderr("error: template \"old\" not found\n");
+    qk(&["add-template", "rs", "--projects-dir", "a"])
+        .failure()
+        .stderr("error: template \"rs\" already exists\n");
+    qk(&["set", "projects_dir", "a"])
+        .failure()
+        .stderr("error: `projects_dir` can only be set in a template\n");
+}
+
+#[test]
+fn test_config_invalid_changes_are_not_saved() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file.write_str("templates = 1\n").unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .args(["config", "set", "editor", "vi"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with(
+            "error: the changes would make the config invalid",
+        ));
+    config_file.assert("templates = 1\n");
+}
+
+#[test]
+fn test_config_edit() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file.write_str("editor = 'nonexistent'\n").unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .args(["--editor", "cat", "config", "edit"])
+        .assert()
+        .success()
+        .stdout("editor = 'nonexistent'\n");
+}
+
+#[test]
+fn test_config_show() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(
+            "\
+            shell = 'bash'
+
+            [templates]
+            a = '/a'
+
+            [templates.rs]
+            projects_dir = '/rs'
+            editor = 'code'
+            commands = ['cargo init', ['git', 'init']]
+            ",
+        )
+        .unwrap();
+    let path = config_file.path().display();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env_remove("VISUAL")
+        .env("EDITOR", "vim")
+        .args(["config", "show"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "\
+env:QK_CONFIG_PATH\tconfig={path}
+env:EDITOR\teditor=vim
+file:{path}\tshell=bash
+file:{path} (templates.a)\ttemplates.a.projects_dir=/a
+file:{path} (templates.rs)\ttemplates.rs.projects_dir=/rs
+file:{path} (templates.rs)\ttemplates.rs.commands[0]=\"cargo init\"
+file:{path} (templates.rs)\ttemplates.rs.commands[1]=[\"git\", \"init\"]
+"
+        ))
+        .stderr("");
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .args(["--config", &path.to_string(), "--editor", "nano"])
+        .args(["config", "show", "rs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "\
+command line:--config\tconfig={path}
+command line:--editor\teditor=nano
+file:{path}\tshell=bash
+file:{path} (templates.rs)\ttemplates.rs.projects_dir=/rs
+"
+        )));
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .args(["config", "show", "rs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "file:{path} (templates.rs)\teditor=code\n"
+        )));
+}
+
+#[test]
+fn test_config_schema() {
+    Command::cargo_bin("qk")
+        .unwrap()
+        .args(["config", "schema"])
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with("{\n  \"$schema\": "))
+        .stdout(predicate::str::contains("\"title\": \"Config\""))
+        .stderr("");
+}
+
+#[test]
+fn test_config_edit_invalid_changes_are_not_saved() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file.write_str("editor = 'vi'\n").unwrap();
+
+    // The editor replaces the file with `contents`
+    let edit = |contents: &str, stdin: &str| {
+        let new_file = temp.child("new.toml");
+        new_file.write_str(contents).unwrap();
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .arg("--editor")
+            .arg(format!("cp {}", new_file.path().display()))
+            .args(["config", "edit"])
+            .write_stdin(stdin)
+            .assert()
+    };
+
+    edit("garbage [[[", "")
+        .failure()
+        .stderr(predicate::str::starts_with(
+            "error: the config is invalid: ",
+        ))
+        .stderr(predicate::str::ends_with(
+            "error: the config is invalid, the changes were not saved\n",
+        ));
+    edit("garbage [[[", "n\n").failure();
+    config_file.assert("editor = 'vi'\n");
+    temp.child(".qk.edit.toml")
+        .assert(predicate::path::missing());
+
+    edit("editor = 'nano'\n", "").success();
+    config_file.assert("editor = 'nano'\n");
+    temp.child(".qk.edit.toml")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_config_add_invalid_template() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file.write_str("editor = 'vi'\n").unwrap();
+
+    let add_template = |name: &str, projects_dir: &str| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .args([
+                "config",
+                "add-template",
+                name,
+                "--projects-dir",
+                projects_dir,
+            ])
+            .assert()
+            .failure()
+    };
+
+    add_template("config", "/tmp/x").stderr(
+        "\
+error: the changes would make the config invalid
+
+Caused by:
+    a template can't be named `config`, it's the `qk config` command
+",
+    );
+    add_template("rs", "${vars.nope}/rs").stderr(predicate::str::contains(
+        "unknown variable `nope`, it isn't in `vars`",
+    ));
+    config_file.assert("editor = 'vi'\n");
+}
@@ -2,6 +2,7 @@ use std::fs;
 
 use assert_cmd::Command;
 use assert_fs::prelude::*;
+use predicates::prelude::*;
 
 #[test]
 fn test_create_project() {
@@ -33,6 +34,7 @@ fn test_create_project() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example")
         .arg("one")
         .assert()
@@ -86,6 +88,7 @@ fn test_create_project_overwrite() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example")
         .arg("one")
         .arg("--overwrite")
@@ -135,6 +138,7 @@ fn test_create_project_custom_args_positional_required() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example2")
         .arg("two")
         .arg("--editor")
@@ -175,6 +179,7 @@ fn test_create_project_custom_args_positional_required_missing() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example2")
         .arg("two")
         .arg("--editor")
@@ -217,6 +222,7 @@ fn test_create_project_custom_args_positional_short() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("mytemplate")
         .arg("myproject")
         .arg("hello")
@@ -261,6 +267,7 @@ fn test_create_project_custom_args_positional_optional() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example")
         .arg("myproject")
         .arg("--editor")
@@ -301,6 +308,7 @@ fn test_create_project_custom_args_positional_optional_missing() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .arg("example")
         .arg("myproject")
         .arg("--editor")
@@ -338,6 +346,7 @@ fn test_create_project_custom_args_positional_empty_values_allowed() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env_remove("VISUAL")
         .env("EDITOR", "echo")
         .arg("example")
@@ -384,6 +393,7 @@ fn test_create_project_custom_args_positional_empty_values_not_allowed() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env_remove("VISUAL")
         .env("EDITOR", "echo")
         .arg("example")
@@ -429,6 +439,7 @@ fn test_create_project_custom_args_option_required() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env("VISUAL", "echo")
         .arg("example")
         .arg("project1")
@@ -467,6 +478,7 @@ fn test_create_project_custom_args_option_required_missing() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env("VISUAL", "echo")
         .arg("example")
         .arg("project1")
@@ -513,6 +525,7 @@ fn test_create_project_custom_args_option_optional_only_short() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env("VISUAL", "echo")
         .arg("example")
         .arg("project1")
@@ -559,6 +572,7 @@ fn test_create_project_custom_args_option_optional_missing() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env("VISUAL", "echo")
         .arg("example")
         .arg("project1")
@@ -602,6 +616,7 @@ fn test_create_project_custom_args_flag() {
     Command::cargo_bin("qk")
         .unwrap()
         .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
         .env_remove("VISUAL")
         .env("EDITOR", "echo")
         .arg("example")
@@ -621,3 +636,1137 @@ flag: --flag other flag: --other
         ))
         .stderr("");
 }
+
+#[test]
+fn test_create_project_argv_command() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                ['mkdir', '#{{@project}}'],
+                ['touch', '#{{@project_dir}}/#{{1:file!}}'],
+                ['echo', '#{{flag?}}', '$QK_PROJECT_NAME']
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .arg("my file.txt")
+        .assert()
+        .success()
+        .stdout(format!(
+            "\
+$ mkdir project1
+$ touch '{path}/project1/my file.txt'
+$ echo '$QK_PROJECT_NAME'
+$QK_PROJECT_NAME
+",
+            path = projects_dir_path.to_string_lossy()
+        ))
+        .stderr("");
+    projects_dir
+        .child("project1")
+        .child("my file.txt")
+        .assert("");
+}
+
+#[test]
+fn test_create_project_failed_command_log() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+    let state_dir = temp.child("state");
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                {{ run = 'echo asked', interactive = true }},
+                'echo out',
+                'echo err >&2; exit 3',
+                'echo unreachable'
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    let log_path = state_dir
+        .child("qk")
+        .child("logs")
+        .child("example")
+        .child("project1.log");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", state_dir.path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .failure()
+        .stdout("$ echo asked\nasked\n$ echo out\nout\n$ echo err >&2; exit 3\n")
+        .stderr(format!(
+            "\
+err
+error: failed creating the project, see the log at {}
+
+Caused by:
+    command exited with non-successful exit status: 3
+",
+            log_path.path().to_string_lossy()
+        ));
+    log_path.assert(
+        predicates::str::is_match(
+            "\
+^\\$ echo asked
+\\[the output of interactive commands isn't logged\\]
+\\[exit status: 0, took .+\\]
+
+\\$ echo out
+out
+\\[exit status: 0, took .+\\]
+
+\\$ echo err >&2; exit 3
+err
+\\[exit status: 3, took .+\\]
+
+error: command exited with non-successful exit status: 3
+$",
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_create_project_command_options() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                {{ run = 'echo x >> attempts; [ $(wc -l < attempts) -ge 2 ]', retries = 2 }},
+                {{ run = 'sleep 5', timeout = '100ms', allow_failure = true }},
+                {{ run = ['does-not-exist-xyz'], allow_failure = true, retries = 1 }},
+                {{ run = ['mkdir', '#{{@project}}'] }},
+                {{ run = 'pwd', cwd = '#{{@project}}' }},
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .success()
+        .stdout(format!(
+            "\
+$ echo x >> attempts; [ $(wc -l < attempts) -ge 2 ]
+$ echo x >> attempts; [ $(wc -l < attempts) -ge 2 ]
+$ sleep 5
+$ does-not-exist-xyz
+$ does-not-exist-xyz
+$ mkdir project1
+$ pwd
+{}/project1
+",
+            projects_dir_path.to_string_lossy()
+        ))
+        .stderr(
+            "\
+warning: command exited with non-successful exit status: 1, retrying (1/2)
+warning: command timed out after 100ms, continuing since it's allowed to fail
+warning: failed running command: No such file or directory (os error 2), retrying (1/1)
+warning: failed running command: No such file or directory (os error 2), continuing since it's allowed to fail
+",
+        );
+}
+
+/// Returns whether the process with the id written in `pid_file` is gone, waiting a bit for it
+#[cfg(target_os = "linux")]
+fn process_is_gone(pid_file: &std::path::Path) -> bool {
+    let pid = fs::read_to_string(pid_file).unwrap();
+    let stat = format!("/proc/{}/stat", pid.trim());
+    for _ in 0..100 {
+        // A killed process that wasn't reaped yet is a zombie, which is also gone
+        match fs::read_to_string(&stat) {
+            Ok(stat) if !stat.contains(") Z ") => {}
+            _ => return true,
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    false
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_create_project_timeout_kills_child_processes() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(&format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                {{ run = 'sleep 30 & echo $! > sleep.pid; wait', timeout = '200ms', allow_failure = true }},
+                {{ run = 'sleep 30 & echo $! > killed.pid; kill -9 $$', timeout = '1m', allow_failure = true }},
+            ]
+            ",
+            temp.path().display()
+        ))
+        .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .args(["example", "project1", "--editor", ""])
+        .assert()
+        .success()
+        .stderr(
+            "\
+warning: command timed out after 200ms, continuing since it's allowed to fail
+warning: command exited with non-successful signal: 9 (SIGKILL), continuing since it's allowed to fail
+",
+        );
+    assert!(process_is_gone(temp.child("sleep.pid").path()));
+    assert!(process_is_gone(temp.child("killed.pid").path()));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_create_project_stopped_kills_child_processes() {
+    use std::os::unix::process::ExitStatusExt;
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let temp = assert_fs::TempDir::new().unwrap();
+        let config_file = temp.child("qk.toml");
+        config_file
+            .write_str(&format!(
+                "\
+                shell = 'sh'
+
+                [templates.example]
+                projects_dir = '{}'
+                commands = [
+                    {{ run = 'sleep 30 & echo $! > sleep.pid; wait', timeout = '1m' }},
+                ]
+                ",
+                temp.path().display()
+            ))
+            .unwrap();
+        let mut qk = std::process::Command::new(assert_cmd::cargo::cargo_bin("qk"))
+            .env("QK_CONFIG_PATH", config_file.path())
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .args(["example", "project1", "--editor", ""])
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid_file = temp.child("sleep.pid");
+        for _ in 0..500 {
+            if fs::read_to_string(pid_file.path()).is_ok_and(|pid| pid.ends_with('\n')) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        // SAFETY: kill only sends a signal
+        unsafe { libc::kill(qk.id() as libc::pid_t, signal) };
+        assert_eq!(qk.wait().unwrap().signal(), Some(signal));
+        assert!(process_is_gone(pid_file.path()), "signal {signal}");
+    }
+}
+
+#[test]
+fn test_create_project_parallel_commands() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo first',
+                {{ parallel = [
+                    'echo one; echo two',
+                    {{ run = ['echo', 'three'], name = 'third' }},
+                ] }},
+                'echo last',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    let output = Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[..2], ["$ echo first", "first"]);
+    assert_eq!(lines[lines.len() - 2..], ["$ echo last", "last"]);
+    let mut parallel_lines = lines[2..lines.len() - 2].to_vec();
+    parallel_lines.sort();
+    assert_eq!(
+        parallel_lines,
+        [
+            "[echo one; echo two] $ echo one; echo two",
+            "[echo one; echo two] one",
+            "[echo one; echo two] two",
+            "[third] $ echo three",
+            "[third] three",
+        ]
+    );
+}
+
+#[test]
+fn test_create_project_parallel_commands_failure() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                {{ parallel = [
+                    {{ run = 'sleep 5 & echo $! > ../sleep.pid; wait; echo late', name = 'slow' }},
+                    {{ run = 'sleep 0.1; exit 4', name = 'failing' }},
+                ] }},
+                'echo unreachable',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    let start = std::time::Instant::now();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .failure()
+        .stdout(predicates::str::contains("[slow] late").not())
+        .stdout(predicates::str::contains("$ echo unreachable").not())
+        .stderr(predicates::str::contains(
+            "\
+Caused by:
+    0: parallel command \"failing\" failed, the other commands in its group were stopped
+    1: command exited with non-successful exit status: 4
+",
+        ));
+    assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    #[cfg(target_os = "linux")]
+    assert!(process_is_gone(temp.child("sleep.pid").path()));
+}
+
+#[test]
+fn test_create_project_conditional_commands() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo build#{{docker? && , docker build -t #{{@project}} .}}#{{tag && :#{{tag}}\\}}}}',
+                {{ run = 'echo ci', when = 'ci' }},
+                {{ run = 'echo tagged', when = 'tag' }},
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .success()
+        .stdout("$ echo build\nbuild\n")
+        .stderr("");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project2")
+        .arg("--editor")
+        .arg("")
+        .arg("--")
+        .arg("--docker")
+        .arg("--ci")
+        .arg("--tag")
+        .arg("v1")
+        .assert()
+        .success()
+        .stdout(
+            "\
+$ echo build, docker build -t project2 .:v1}
+build, docker build -t project2 .:v1}
+$ echo ci
+ci
+$ echo tagged
+tagged
+",
+        )
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_multiple_values() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo #{{feature,f+}} #{{tag+\",\"}} #{{1:crates...}}',
+                ['echo', '#{{1:crates...}}', '#{{feature,f+}}', 'x#{{1:crates...}}'],
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .arg("serde")
+        .arg("clap")
+        .arg("--")
+        .arg("--feature")
+        .arg("a")
+        .arg("-f")
+        .arg("b")
+        .arg("--tag")
+        .arg("c")
+        .arg("--tag")
+        .arg("d")
+        .assert()
+        .success()
+        .stdout(
+            "\
+$ echo --feature a --feature b c,d serde clap
+--feature a --feature b c,d serde clap
+$ echo serde clap --feature a --feature b 'xserde clap'
+serde clap --feature a --feature b xserde clap
+",
+        )
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_flag_text_and_negatable_flags() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo build #{{verbose,v?=-vvv}}',
+                'echo #{{[no-]git? && git init}}',
+                {{ run = 'echo readme', when = '[no-]readme' }},
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .arg("--")
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(
+            "\
+$ echo build -vvv
+build -vvv
+$ echo git init
+git init
+$ echo readme
+readme
+",
+        )
+        .stderr("");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project2")
+        .arg("--editor")
+        .arg("")
+        .arg("--")
+        .arg("--no-git")
+        .arg("--no-readme")
+        .assert()
+        .success()
+        .stdout("$ echo build \nbuild\n$ echo \n\n")
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_typed_custom_args() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo #{{1:name!~^[a-z][a-z0-9_]*$}} #{{port:int}} #{{debug:bool}} #{{version:semver}}',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .arg("my_app")
+        .arg("--")
+        .arg("--port")
+        .arg("8080")
+        .arg("--debug")
+        .arg("yes")
+        .arg("--version")
+        .arg("1.2.3")
+        .assert()
+        .success()
+        .stdout("$ echo my_app 8080 true 1.2.3\nmy_app 8080 true 1.2.3\n")
+        .stderr("");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project2")
+        .arg("--editor")
+        .arg("")
+        .arg("my_app")
+        .arg("--")
+        .arg("--port")
+        .arg("http")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "invalid value 'http' for '--port <port>'",
+        ));
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project3")
+        .arg("--editor")
+        .arg("")
+        .arg("MyApp")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::contains(
+            "value doesn't match the pattern `^[a-z][a-z0-9_]*$`",
+        ));
+    projects_dir
+        .child("project2")
+        .assert(predicate::path::missing());
+}
+
+#[test]
+fn test_create_project_custom_args_env() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo #{{author,a@QK_TEST_AUTHOR!}} #{{ci@QK_TEST_CI?}}',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .env("QK_TEST_AUTHOR", "someone")
+        .env("QK_TEST_CI", "true")
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .success()
+        .stdout("$ echo someone --ci\nsomeone --ci\n")
+        .stderr("");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .env("QK_TEST_AUTHOR", "someone")
+        .arg("example")
+        .arg("project2")
+        .arg("--editor")
+        .arg("")
+        .arg("--")
+        .arg("-a")
+        .arg("other")
+        .assert()
+        .success()
+        .stdout("$ echo other \nother\n")
+        .stderr("");
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("project3")
+        .arg("--")
+        .arg("--help")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[env: QK_TEST_AUTHOR=]"));
+}
+
+#[test]
+fn test_create_project_builtins() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+    let git_config = temp.child("gitconfig");
+    git_config
+        .write_str("[user]\n\tname = Some One\n\temail = someone@example.com\n")
+        .unwrap();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            shell = 'sh'
+
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                ['echo', '#{{@template}} #{{@git_name}} <#{{@git_email}}> #{{@os}}'],
+                'echo $QK_TEMPLATE $QK_GIT_EMAIL',
+                'test #{{@date:%Y}} = $QK_YEAR && test #{{@uuid}} = $QK_UUID',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .env("GIT_CONFIG_GLOBAL", git_config.path())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .current_dir(temp.path())
+        .arg("example")
+        .arg("project1")
+        .arg("--editor")
+        .arg("")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!(
+            "\
+$ echo 'example Some One <someone@example.com> {os}'
+example Some One <someone@example.com> {os}
+$ echo $QK_TEMPLATE $QK_GIT_EMAIL
+example someone@example.com
+",
+            os = std::env::consts::OS
+        )))
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_expressions() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [
+                'echo #{{1:crates...}}#{{lib?}} {{{{@project | pascal}}}}',
+                'echo {{{{#if lib}}}}lib{{{{else}}}}bin{{{{/if}}}}:{{{{#each crates}}}} -p {{{{this | upper}}}}{{{{/each}}}} \\{{{{x}}}}',
+            ]
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_path)
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("example")
+        .arg("my-project")
+        .arg("--editor")
+        .arg("")
+        .arg("serde")
+        .arg("clap")
+        .assert()
+        .success()
+        .stdout(
+            "\
+$ echo serde clap MyProject
+serde clap MyProject
+$ echo bin: -p SERDE -p CLAP {{x}}
+bin: -p SERDE -p CLAP {{x}}
+",
+        )
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_arg_relations() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = ['echo #{{sqlite?}}#{{postgres?}} #{{db-url}} #{{user}}']
+            groups = [{{ name = 'database', args = ['sqlite', 'postgres'], required = true }}]
+            requires = {{ db-url = ['user'] }}
+            conflicts = {{ db-url = ['sqlite'] }}
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_path)
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .arg("example")
+            .arg("my-project")
+            .arg("--editor")
+            .arg("")
+            .arg("--")
+            .args(args)
+            .assert()
+    };
+
+    qk(&[]).failure().stderr(predicates::str::contains(
+        "the following required arguments were not provided",
+    ));
+    qk(&["--sqlite", "--postgres"])
+        .failure()
+        .stderr(predicates::str::contains("cannot be used with"));
+    qk(&["--postgres", "--db-url", "db"])
+        .failure()
+        .stderr(predicates::str::contains("--user <user>"));
+    qk(&["--sqlite", "--db-url", "db", "--user", "me"])
+        .failure()
+        .stderr(predicates::str::contains(
+            "the argument '--sqlite' cannot be used with '--db-url <db-url>'",
+        ));
+    projects_dir
+        .child("my-project")
+        .assert(predicates::path::missing());
+
+    qk(&["--postgres", "--db-url", "db", "--user", "me"])
+        .success()
+        .stdout("$ echo --postgres db me\n--postgres db me\n");
+}
+
+#[test]
+fn test_create_project_declared_args() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let config_path = config_file.path();
+
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    let projects_dir_path = projects_dir.path();
+
+    fs::write(
+        config_path,
+        format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = ['echo #{{edition}} #{{lib}}', 'echo {{{{crate}}}} #{{edition}}']
+
+            [templates.example.args]
+            crate = {{ kind = 'positional', index = 1, required = true, help = 'Name of the crate' }}
+            edition = {{ short = 'e', default = '2021', choices = ['2018', '2021'] }}
+            lib = {{ kind = 'flag', short = 'l' }}
+        ",
+            projects_dir_path.to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_path)
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .arg("example")
+            .arg("my-project")
+            .arg("--editor")
+            .arg("")
+            .arg("--")
+            .args(args)
+            .assert()
+    };
+
+    qk(&["--help"])
+        .success()
+        .stdout(predicates::str::contains("<crate>  Name of the crate"))
+        .stdout(predicates::str::contains(
+            "[default: 2021] [possible values: 2018, 2021]",
+        ));
+    qk(&["serde", "-e", "2015"])
+        .failure()
+        .stderr(predicates::str::contains("invalid value '2015'"));
+    qk(&["serde", "-l"])
+        .success()
+        .stdout("$ echo 2021 --lib\n2021 --lib\n$ echo serde 2021\nserde 2021\n")
+        .stderr("");
+}
+
+#[test]
+fn test_create_project_with_default_template_and_aliases() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let projects_dir = temp.child("projects");
+    projects_dir.create_dir_all().unwrap();
+    config_file
+        .write_str(&format!(
+            "\
+            editor = ''
+            default_template = 'rs'
+
+            [templates.rust-projects]
+            aliases = ['rs', 'rust']
+            projects_dir = '{}'
+            commands = ['mkdir $QK_PROJECT_NAME']
+            ",
+            projects_dir.path().display()
+        ))
+        .unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .args(args)
+            .assert()
+    };
+
+    qk(&["one"])
+        .success()
+        .stdout("$ mkdir $QK_PROJECT_NAME\n")
+        .stderr("");
+    qk(&["rust", "two"]).success();
+    qk(&["-L", "rs"]).success().stdout("one\ntwo\n");
+
+    config_file.write_str("[templates]\nrs = '/rs'\n").unwrap();
+    qk(&["one"])
+        .failure()
+        .code(2)
+        .stderr(predicate::str::starts_with(
+            "\
+error: the following required arguments were not provided:
+  <project>
+",
+        ));
+}
+
+#[test]
+fn test_create_project_with_vars_and_env() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(&format!(
+            "\
+            editor = ''
+
+            [vars]
+            code = '{}'
+            org = 'acme'
+
+            [templates.svc]
+            projects_dir = '${{vars.code}}/services'
+            env = {{ ORG = '${{vars.org}}' }}
+            commands = ['echo ${{vars.org}}/$QK_PROJECT_NAME $ORG > $QK_PROJECT_NAME.txt']
+            ",
+            temp.path().display()
+        ))
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .args(["svc", "payments"])
+        .assert()
+        .success()
+        .stdout("$ echo acme/$QK_PROJECT_NAME $ORG > $QK_PROJECT_NAME.txt\n")
+        .stderr("");
+    temp.child("services/payments.txt")
+        .assert("acme/payments acme\n");
+
+    config_file
+        .write_str("[templates]\nsvc = '${vars.code}/services'\n")
+        .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .arg("-T")
+        .assert()
+        .failure()
+        .stderr(
+            "\
+error: failed loading config
+
+Caused by:
+    0: invalid template \"svc\"
+    1: unknown variable `code`, it isn't in `vars`
+",
+        );
+}
+
+#[test]
+fn test_create_project_named_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let projects_dir = temp.child("projects");
+    projects_dir.create_dir_all().unwrap();
+    config_file
+        .write_str(&format!(
+            "\
+            [templates.rs]
+            projects_dir = '{}'
+            commands = ['mkdir $QK_PROJECT_NAME']
+            ",
+            projects_dir.path().display()
+        ))
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .args(["rs", "config", "-e", ""])
+        .assert()
+        .success()
+        .stdout("$ mkdir $QK_PROJECT_NAME\n")
+        .stderr("");
+    projects_dir
+        .child("config")
+        .assert(predicate::path::is_dir());
+}
+
+#[test]
+fn test_create_project_argv_command_empty_argument() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    let projects_dir = temp.child("example");
+    projects_dir.create_dir_all().unwrap();
+    config_file
+        .write_str(&format!(
+            "\
+            [templates.example]
+            projects_dir = '{}'
+            commands = [['printf', '[%s]', 'x', '', 'y', '#{{flag?}}', '#{{message*}}']]
+            ",
+            projects_dir.path().display()
+        ))
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .args(["example", "project1", "-e", "", "--", "--message", ""])
+        .assert()
+        .success()
+        .stdout("$ printf '[%s]' x '' y ''\n[x][][y][]")
+        .stderr("");
+}
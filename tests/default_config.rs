@@ -1,32 +1,108 @@
 use assert_cmd::Command;
 use assert_fs::prelude::*;
+use predicates::prelude::*;
 
 #[test]
-fn test_create_default_config() {
+fn test_no_default_config_is_written() {
     let temp = assert_fs::TempDir::new().unwrap();
 
     Command::cargo_bin("qk")
         .unwrap()
         .arg("-T")
         .env("HOME", temp.path())
+        .env_remove("XDG_CONFIG_HOME")
         .assert()
+        .failure()
+        .stdout("")
+        .stderr("error: no templates yet\n");
+
+    temp.child(".config").assert(predicate::path::missing());
+}
+
+#[test]
+fn test_config_init() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child(".config").child("qk").child("qk.toml");
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("HOME", temp.path())
+            .env_remove("XDG_CONFIG_HOME")
+            .args(args)
+            .assert()
+    };
+
+    qk(&["config", "init", "--from", "go", "--from", "cargo"])
         .success()
-        .stdout("example\n")
+        .stdout(format!(
+            "go: ~/projects/go\nrs: ~/projects/rs\ncreated {}\n",
+            config_file.path().display()
+        ))
         .stderr("");
+    config_file.assert(
+        "\
+[templates.go]
+projects_dir = \"~/projects/go\"
+commands = [\"mkdir $QK_PROJECT_NAME && cd $QK_PROJECT_NAME && go mod init #{1:module!}\"]
 
-    temp.child(".config").child("qk").child("qk.toml").assert(
-        "editor = \"vi\"
-shell = \"sh\"
-
-[templates.example]
-projects_dir = \"/path/to/example/\"
-editor = \"code\"
-shell = \"bash\"
-commands = [
-    \"echo hello\",
-    \"echo $PWD\",
-    \"echo $QK_PROJECT_NAME\",
-]
+[templates.rs]
+projects_dir = \"~/projects/rs\"
+commands = [\"cargo new $QK_PROJECT_NAME #{lib?}\"]
 ",
     );
+    qk(&["-T"]).success().stdout("go\nrs\n");
+
+    qk(&["config", "init", "--from", "npm"])
+        .failure()
+        .stderr(format!(
+            "error: {} already exists, use --force to overwrite it\n",
+            config_file.path().display()
+        ));
+    qk(&["config", "init", "--from", "npm", "--force"]).success();
+    qk(&["-T"]).success().stdout("js\n");
+}
+
+#[test]
+fn test_config_init_interactive() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.json");
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .args(["config", "init", "-i", "--from", "npm", "--from", "python"])
+        .write_stdin("\n/js\nn\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "Add template js for npm? [y]: Directory of its projects [~/projects/js]: js: /js\n\
+             Add template py for python? [y]: created",
+        ));
+    config_file.assert(predicate::str::contains("\"projects_dir\": \"/js\""));
+    config_file.assert(predicate::str::contains("py").not());
+}
+
+#[test]
+fn test_config_init_without_toolchains_or_answers() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .env("PATH", temp.path())
+            .args(args)
+            .assert()
+    };
+
+    qk(&["config", "init"])
+        .failure()
+        .stdout("")
+        .stderr("error: no toolchains found, choose the templates to add with --from\n");
+    qk(&["config", "init", "-i", "--from", "cargo"])
+        .failure()
+        .stderr("error: no answer to \"Add template rs for cargo?\", the input ended\n");
+    config_file.assert(predicate::path::missing());
 }
@@ -17,9 +17,11 @@ error: the following required arguments were not provided:
   <template>
 
 Usage: qk [OPTIONS] <template> <project> [custom-args]...
+    qk [OPTIONS] <project>
     qk [OPTIONS] -L <template>
     qk [OPTIONS] -E <template>
-    qk [OPTIONS] -T
+    qk [OPTIONS] -T [--long] [--tag <tag>]...
+    qk [OPTIONS] config <command>
     qk --help
     qk --version
 
@@ -170,3 +172,53 @@ fn test_list_projects_short_flag_some_templates() {
         .stdout("one\ntwo\n")
         .stderr("");
 }
+
+#[test]
+fn test_list_projects_expanded_projects_dir() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("config").child("qk.toml");
+    config_file.touch().unwrap();
+    let config_path = config_file.path();
+
+    temp.child("config/relative/one").create_dir_all().unwrap();
+    temp.child("projects/two").create_dir_all().unwrap();
+
+    fs::write(
+        config_path,
+        "\
+        [templates]
+        relative = 'relative'
+        env = '${QK_TEST_PROJECTS}/projects'
+        ",
+    )
+    .unwrap();
+
+    let qk = |template: &str| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_path)
+            .env("QK_TEST_PROJECTS", temp.path())
+            .env_remove("QK_TEST_MISSING")
+            .arg("-L")
+            .arg(template)
+            .assert()
+    };
+
+    qk("relative").success().stdout("one\n").stderr("");
+    qk("env").success().stdout("two\n").stderr("");
+
+    fs::write(
+        config_path,
+        "templates.missing = '$QK_TEST_MISSING/projects'",
+    )
+    .unwrap();
+    qk("missing").failure().stdout("").stderr(
+        "\
+error: failed loading config
+
+Caused by:
+    0: invalid projects_dir in template \"missing\"
+    1: failed expanding path \"$QK_TEST_MISSING/projects\", environment variable QK_TEST_MISSING is not set
+",
+    );
+}
@@ -64,3 +64,185 @@ fn test_list_templates_short_flag_some_templates() {
         .stdout("example\nexample2\nhello\n")
         .stderr("");
 }
+
+#[test]
+fn test_list_templates_yaml_and_json() {
+    let temp = assert_fs::TempDir::new().unwrap();
+
+    let yaml_file = temp.child("qk.yaml");
+    yaml_file
+        .write_str("templates:\n  hello: /path/to/hello\n  example:\n    projects_dir: /example\n")
+        .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", yaml_file.path())
+        .arg("-T")
+        .assert()
+        .success()
+        .stdout("example\nhello\n")
+        .stderr("");
+
+    let json_file = temp.child("config.json");
+    json_file
+        .write_str(r#"{ "templates": { "hello": "/path/to/hello" } }"#)
+        .unwrap();
+    Command::cargo_bin("qk")
+        .unwrap()
+        .arg("--config")
+        .arg(json_file.path())
+        .arg("-T")
+        .assert()
+        .success()
+        .stdout("hello\n")
+        .stderr("");
+}
+
+#[test]
+fn test_list_templates_default_yaml_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child(".config").child("qk").child("qk.yml");
+    config_file
+        .write_str("templates:\n  hello: /path/to/hello\n")
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("HOME", temp.path())
+        .env_remove("XDG_CONFIG_HOME")
+        .arg("-T")
+        .assert()
+        .success()
+        .stdout("hello\n")
+        .stderr("");
+    temp.child(".config/qk/qk.toml")
+        .assert(predicates::path::missing());
+}
+
+#[test]
+fn test_list_templates_table_and_tags() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(
+            "\
+            [templates.rs]
+            description = 'Rust crates'
+            tags = ['rust', 'work']
+            projects_dir = 'rs'
+            commands = ['cargo new #{1:name!} #{lib?} #{edition,e}']
+
+            [templates.py]
+            tags = ['work']
+            projects_dir = 'py'
+            ",
+        )
+        .unwrap();
+    temp.child("rs/a").create_dir_all().unwrap();
+    temp.child("rs/b").create_dir_all().unwrap();
+    let py = format!("{}/py", temp.path().display());
+    let rs = format!("{}/rs", temp.path().display());
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .arg("-T")
+            .args(args)
+            .assert()
+    };
+
+    qk(&["--long"]).success().stdout(format!(
+        "\
+NAME  DESCRIPTION  {:w$}  PROJECTS  ARGS
+py                 {py}  0
+rs    Rust crates  {rs}  2         <name> [--edition <edition>] [--lib]
+",
+        "PROJECTS_DIR",
+        w = rs.len(),
+    ));
+    qk(&["--tag", "work"]).success().stdout("py\nrs\n");
+    qk(&["--tag", "work", "--tag", "rust"])
+        .success()
+        .stdout("rs\n");
+    qk(&["--tag", "python"])
+        .failure()
+        .stderr("error: no templates with these tags\n");
+}
+
+#[test]
+fn test_list_templates_options_require_list_templates() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(&format!(
+            "\
+            editor = ''
+
+            [templates.rs]
+            projects_dir = '{}'
+            commands = ['echo #{{lib,l?}}']
+            ",
+            temp.path().display()
+        ))
+        .unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .args(args)
+            .assert()
+    };
+
+    qk(&["rs", "foo", "-l"])
+        .failure()
+        .code(2)
+        .stdout("")
+        .stderr(predicates::str::starts_with(
+            "error: unexpected argument '-l' found",
+        ));
+    qk(&["--tag", "x", "rs"])
+        .failure()
+        .code(2)
+        .stderr(predicates::str::starts_with(
+            "\
+error: the following required arguments were not provided:
+  --list-templates
+",
+        ));
+    qk(&["rs", "foo", "--", "-l"])
+        .success()
+        .stdout("$ echo --lib\n--lib\n");
+}
+
+#[test]
+fn test_list_templates_invalid_aliases() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(
+            "\
+            [templates]
+            a = { projects_dir = '/a', aliases = ['x'] }
+            b = { projects_dir = '/b', aliases = ['x'] }
+            ",
+        )
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .arg("-T")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(
+            "\
+error: failed loading config
+
+Caused by:
+    alias `x` is ambiguous, both `a` and `b` use it
+",
+        );
+}
@@ -0,0 +1,158 @@
+use assert_cmd::Command;
+use assert_fs::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn test_local_config_is_merged_over_global_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(
+            "\
+            editor = 'vi'
+            shell = 'sh'
+            trusted_dirs = ['repo']
+
+            [templates]
+            rs = '/rs'
+            svc = '/global/services'
+            ",
+        )
+        .unwrap();
+
+    let repo = temp.child("repo");
+    let local_config = repo.child(".qk.toml");
+    local_config
+        .write_str(
+            "\
+            editor = ''
+
+            [templates.svc]
+            projects_dir = 'services'
+            commands = ['mkdir $QK_PROJECT_NAME']
+            ",
+        )
+        .unwrap();
+    let subdir = repo.child("docs/guides");
+    subdir.create_dir_all().unwrap();
+
+    let qk = |args: &[&str]| {
+        Command::cargo_bin("qk")
+            .unwrap()
+            .env("QK_CONFIG_PATH", config_file.path())
+            .env("XDG_STATE_HOME", temp.child("state").path())
+            .current_dir(subdir.path())
+            .args(args)
+            .assert()
+    };
+
+    let notices = format!(
+        "using the local config {}\nwarning: the local config replaces the template \"svc\"\n",
+        local_config.path().display()
+    );
+    qk(&["-T"])
+        .success()
+        .stdout("rs\nsvc\n")
+        .stderr(notices.clone());
+    qk(&["svc", "payments"])
+        .success()
+        .stdout("$ mkdir $QK_PROJECT_NAME\n")
+        .stderr(notices);
+    repo.child("services/payments")
+        .assert(predicate::path::is_dir());
+
+    qk(&["config", "show", "svc"])
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "default\tlocal_config={}\nfile:{}\teditor=\nfile:{}\tshell=sh\n",
+            local_config.path().display(),
+            local_config.path().display(),
+            config_file.path().display(),
+        )));
+}
+
+#[test]
+fn test_invalid_local_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str("trusted_dirs = ['.']\n[templates]\nrs = '/rs'\n")
+        .unwrap();
+    temp.child(".qk.json").write_str("{").unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .env("XDG_STATE_HOME", temp.child("state").path())
+        .current_dir(temp.path())
+        .arg("-T")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(predicate::str::starts_with(format!(
+            "using the local config {}\nerror: failed loading local config {}",
+            temp.child(".qk.json").path().display(),
+            temp.child(".qk.json").path().display()
+        )));
+}
+
+#[test]
+fn test_local_config_alias_clashes_with_global_config() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str(
+            "trusted_dirs = ['.']\n[templates]\nrs = { projects_dir = '/rs', aliases = ['r'] }\n",
+        )
+        .unwrap();
+    temp.child(".qk.toml")
+        .write_str("[templates]\nr = '/r'\n")
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .current_dir(temp.path())
+        .arg("-T")
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(format!(
+            "\
+using the local config {}
+error: failed loading local config {}
+
+Caused by:
+    alias `r` of template `rs` is already the name of a template
+",
+            temp.child(".qk.toml").path().display(),
+            temp.child(".qk.toml").path().display()
+        ));
+}
+
+#[test]
+fn test_untrusted_local_config_is_ignored() {
+    let temp = assert_fs::TempDir::new().unwrap();
+    let config_file = temp.child("qk.toml");
+    config_file
+        .write_str("trusted_dirs = ['elsewhere']\n[templates]\nrs = '/rs'\n")
+        .unwrap();
+    let repo = temp.child("repo");
+    repo.child(".qk.toml")
+        .write_str("[templates]\nrs = '/local/rs'\nsvc = 'services'\n")
+        .unwrap();
+
+    Command::cargo_bin("qk")
+        .unwrap()
+        .env("QK_CONFIG_PATH", config_file.path())
+        .current_dir(repo.path())
+        .args(["config", "show", "rs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("projects_dir=/rs\n"))
+        .stdout(predicate::str::contains("local_config").not())
+        .stderr(format!(
+            "warning: ignoring the local config {}, its directory isn't in trusted_dirs\n",
+            repo.child(".qk.toml").path().display()
+        ));
+}
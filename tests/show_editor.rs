@@ -17,9 +17,11 @@ error: the following required arguments were not provided:
   <template>
 
 Usage: qk [OPTIONS] <template> <project> [custom-args]...
+    qk [OPTIONS] <project>
     qk [OPTIONS] -L <template>
     qk [OPTIONS] -E <template>
-    qk [OPTIONS] -T
+    qk [OPTIONS] -T [--long] [--tag <tag>]...
+    qk [OPTIONS] config <command>
     qk --help
     qk --version
 